@@ -0,0 +1,493 @@
+// src/access_log.rs
+
+// dependencies
+use crate::body::{ByteStream, ResponseBody};
+use crate::handler::BoxFuture;
+use crate::log::log_error;
+use crate::middleware::{Middleware, Next};
+use crate::request::AppRequest;
+use crate::response::AppResponse;
+use futures_core::Stream;
+use hyper::Response;
+use hyper::body::Bytes;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+// enum type to represent the layout of an access log line
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    // 127.0.0.1 - - [18/Oct/2026:13:55:36 +0000] "GET /a?b=c HTTP/1.1" 200 2326
+    Common,
+    // Common followed by the quoted Referer and User-Agent
+    Combined,
+    // one JSON object per line with every field, including the duration
+    Json,
+}
+
+// enum type to represent where access log lines are written
+#[derive(Debug)]
+enum LogTarget {
+    Stdout,
+    Stderr,
+    File(RotatingFile),
+}
+
+// struct type to represent access logging middleware, e.g.
+// app.use_middleware(AccessLog::combined().to_file("access.log", 10 * 1024 * 1024)?),
+// every request that passes it is logged with the response the client actually got, after
+// 304, Range and compression handling, and the bytes actually sent. Lines are written by a
+// background thread, so a slow disk never holds up a request
+#[derive(Debug)]
+pub struct AccessLog {
+    format: LogFormat,
+    // handed to the writer thread when the first line is logged
+    target: Mutex<Option<LogTarget>>,
+    // how many rotated files to keep next to the current one
+    max_files: usize,
+    writer: OnceLock<Sender<String>>,
+}
+
+// methods for the AccessLog type
+impl AccessLog {
+    // an access log in the given format written to stdout
+    pub fn new(format: LogFormat) -> Self {
+        AccessLog {
+            format,
+            target: Mutex::new(Some(LogTarget::Stdout)),
+            max_files: 5,
+            writer: OnceLock::new(),
+        }
+    }
+
+    pub fn common() -> Self {
+        AccessLog::new(LogFormat::Common)
+    }
+
+    pub fn combined() -> Self {
+        AccessLog::new(LogFormat::Combined)
+    }
+
+    pub fn json() -> Self {
+        AccessLog::new(LogFormat::Json)
+    }
+
+    pub fn to_stdout(mut self) -> Self {
+        *self.target.get_mut().unwrap() = Some(LogTarget::Stdout);
+        self
+    }
+
+    pub fn to_stderr(mut self) -> Self {
+        *self.target.get_mut().unwrap() = Some(LogTarget::Stderr);
+        self
+    }
+
+    // append to the file at path, once it would grow past max_bytes it's renamed to path.1
+    // (path.1 to path.2 and so on) and a new one started
+    pub fn to_file(mut self, path: impl Into<PathBuf>, max_bytes: u64) -> io::Result<Self> {
+        let file = RotatingFile::open(path.into(), max_bytes)?;
+        *self.target.get_mut().unwrap() = Some(LogTarget::File(file));
+        Ok(self)
+    }
+
+    // how many rotated files to keep, 5 by default, older ones are deleted
+    pub fn max_files(mut self, count: usize) -> Self {
+        self.max_files = count;
+        self
+    }
+
+    // the channel to the writer thread, which is started on first use
+    fn writer(&self) -> Sender<String> {
+        self.writer
+            .get_or_init(|| {
+                let (sender, receiver) = mpsc::channel();
+                let target = self
+                    .target
+                    .lock()
+                    .unwrap()
+                    .take()
+                    .unwrap_or(LogTarget::Stdout);
+                let max_files = self.max_files;
+                std::thread::Builder::new()
+                    .name("access-log".to_string())
+                    .spawn(move || write_lines(receiver, target, max_files))
+                    .expect("failed to start the access log writer");
+                sender
+            })
+            .clone()
+    }
+}
+
+// implement the Middleware trait for the AccessLog type
+impl Middleware for AccessLog {
+    fn handle(&self, req: AppRequest, next: Next) -> BoxFuture<AppResponse> {
+        // the line is written by the server once the final response is known
+        if let Some(pending) = req.extensions.get::<PendingLogs>() {
+            pending.0.lock().unwrap().push(PendingLine {
+                entry: LogEntry::start(&req),
+                format: self.format,
+                writer: self.writer(),
+            });
+        }
+        Box::pin(next.run(req))
+    }
+}
+
+// struct type to represent the access log lines a request is waiting on, the server adds it
+// to every request's extensions and finishes it with the response it sends
+#[derive(Debug, Clone, Default)]
+pub(crate) struct PendingLogs(Arc<Mutex<Vec<PendingLine>>>);
+
+// struct type to represent a line added by one AccessLog
+#[derive(Debug)]
+struct PendingLine {
+    entry: LogEntry,
+    format: LogFormat,
+    writer: Sender<String>,
+}
+
+// methods for the PendingLogs type
+impl PendingLogs {
+    // log the response about to be sent, a streamed body is counted as it goes out and logged
+    // once it ends or the client goes away
+    pub(crate) fn finish(&self, response: Response<ResponseBody>) -> Response<ResponseBody> {
+        let lines = std::mem::take(&mut *self.0.lock().unwrap());
+        if lines.is_empty() {
+            return response;
+        }
+
+        let status = response.status().as_u16();
+        let lines: Vec<ReadyLine> = lines
+            .into_iter()
+            .map(|line| ReadyLine {
+                duration: line.entry.started.elapsed(),
+                line,
+            })
+            .collect();
+
+        let (parts, body) = response.into_parts();
+        let body = match body {
+            ResponseBody::Stream(stream) => ResponseBody::stream(CountingStream {
+                inner: stream,
+                sent: 0,
+                status,
+                lines,
+            }),
+            body => {
+                let sent = body.as_bytes().map_or(0, |bytes| bytes.len() as u64);
+                write_ready(&lines, status, sent);
+                body
+            }
+        };
+        Response::from_parts(parts, body)
+    }
+}
+
+// struct type to represent a line waiting only for the number of bytes sent
+#[derive(Debug)]
+struct ReadyLine {
+    line: PendingLine,
+    // the time until the response was ready, a streamed body may take longer to send
+    duration: Duration,
+}
+
+fn write_ready(lines: &[ReadyLine], status: u16, sent: u64) {
+    for ready in lines {
+        let line = ready
+            .line
+            .entry
+            .format(ready.line.format, status, sent, ready.duration);
+        // the writer only stops if its thread died, which has already been reported
+        let _ = ready.line.writer.send(line);
+    }
+}
+
+// struct type to represent a streamed response body which counts the bytes it yields and
+// writes its log lines when it's dropped, once sent or when the connection closes
+struct CountingStream {
+    inner: ByteStream,
+    sent: u64,
+    status: u16,
+    lines: Vec<ReadyLine>,
+}
+
+// implement the Stream trait for the CountingStream type
+impl Stream for CountingStream {
+    type Item = io::Result<Bytes>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let poll = self.inner.as_mut().poll_next(cx);
+        if let Poll::Ready(Some(Ok(chunk))) = &poll {
+            self.sent += chunk.len() as u64;
+        }
+        poll
+    }
+}
+
+// implement the Drop trait for the CountingStream type
+impl Drop for CountingStream {
+    fn drop(&mut self) {
+        write_ready(&self.lines, self.status, self.sent);
+    }
+}
+
+// write lines as they arrive until every AccessLog sender is gone, a failed write is reported
+// but doesn't affect the response
+fn write_lines(receiver: Receiver<String>, mut target: LogTarget, max_files: usize) {
+    for line in receiver {
+        let result = match &mut target {
+            LogTarget::Stdout => io::stdout().lock().write_all(line.as_bytes()),
+            LogTarget::Stderr => io::stderr().lock().write_all(line.as_bytes()),
+            LogTarget::File(file) => file.write_line(line.as_bytes(), max_files),
+        };
+        if let Err(err) = result {
+            log_error!("failed to write access log: {}", err);
+        }
+    }
+}
+
+// struct type to represent what's known about a request when it arrives, completed with the
+// final response by PendingLogs::finish
+#[derive(Debug, Clone)]
+struct LogEntry {
+    started: Instant,
+    time: SystemTime,
+    client_ip: IpAddr,
+    method: &'static str,
+    uri: String,
+    path: String,
+    query: Option<String>,
+    version: &'static str,
+    referer: Option<String>,
+    user_agent: Option<String>,
+}
+
+// methods for the LogEntry type
+impl LogEntry {
+    fn start(req: &AppRequest) -> Self {
+        let query = req.uri.split_once('?').map(|(_, query)| query.to_string());
+
+        LogEntry {
+            started: Instant::now(),
+            time: SystemTime::now(),
+            client_ip: req.client_ip,
+            method: req.method.as_str(),
+            uri: req.uri.clone(),
+            path: req.path.clone(),
+            query,
+            version: req.version.as_str(),
+            referer: req.headers.get("referer").cloned(),
+            user_agent: req.headers.get("user-agent").cloned(),
+        }
+    }
+
+    fn format(&self, format: LogFormat, status: u16, bytes: u64, duration: Duration) -> String {
+        if format == LogFormat::Json {
+            let optional = |value: &Option<String>| match value {
+                Some(value) => json_string(value),
+                None => "null".to_string(),
+            };
+            return format!(
+                "{{\"time\":\"{}\",\"client_ip\":\"{}\",\"method\":\"{}\",\"path\":{},\"query\":{},\"version\":\"{}\",\"status\":{},\"bytes\":{},\"duration_ms\":{:.3},\"referer\":{},\"user_agent\":{}}}\n",
+                rfc3339(self.time),
+                self.client_ip,
+                self.method,
+                json_string(&self.path),
+                optional(&self.query),
+                self.version,
+                status,
+                bytes,
+                duration.as_secs_f64() * 1000.0,
+                optional(&self.referer),
+                optional(&self.user_agent),
+            );
+        }
+
+        let mut line = format!(
+            "{} - - [{}] \"{} {} {}\" {} {}",
+            self.client_ip,
+            clf_time(self.time),
+            self.method,
+            escape(&self.uri),
+            self.version,
+            status,
+            // as Apache's %b, no body is logged as "-"
+            match bytes {
+                0 => "-".to_string(),
+                bytes => bytes.to_string(),
+            },
+        );
+        if format == LogFormat::Combined {
+            let quoted = |value: &Option<String>| value.as_deref().map_or("-".to_string(), escape);
+            line.push_str(&format!(
+                " \"{}\" \"{}\"",
+                quoted(&self.referer),
+                quoted(&self.user_agent)
+            ));
+        }
+        line.push('\n');
+        line
+    }
+}
+
+// struct type to represent a log file which is rotated by size
+#[derive(Debug)]
+struct RotatingFile {
+    path: PathBuf,
+    file: File,
+    size: u64,
+    max_bytes: u64,
+}
+
+// methods for the RotatingFile type
+impl RotatingFile {
+    fn open(path: PathBuf, max_bytes: u64) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let size = file.metadata()?.len();
+        Ok(RotatingFile {
+            path,
+            file,
+            size,
+            max_bytes,
+        })
+    }
+
+    fn write_line(&mut self, line: &[u8], max_files: usize) -> io::Result<()> {
+        if self.size > 0 && self.size + line.len() as u64 > self.max_bytes {
+            self.rotate(max_files)?;
+        }
+        self.file.write_all(line)?;
+        self.size += line.len() as u64;
+        Ok(())
+    }
+
+    // access.log.1 becomes access.log.2 and so on, the oldest is dropped, then access.log
+    // becomes access.log.1 and a new access.log is started
+    fn rotate(&mut self, max_files: usize) -> io::Result<()> {
+        let numbered = |n: usize| {
+            let mut name = self.path.clone().into_os_string();
+            name.push(format!(".{}", n));
+            PathBuf::from(name)
+        };
+
+        if max_files == 0 {
+            remove_if_exists(&self.path)?;
+        } else {
+            remove_if_exists(&numbered(max_files))?;
+            for n in (1..max_files).rev() {
+                rename_if_exists(&numbered(n), &numbered(n + 1))?;
+            }
+            std::fs::rename(&self.path, numbered(1))?;
+        }
+
+        self.file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+        self.size = 0;
+        Ok(())
+    }
+}
+
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match std::fs::remove_file(path) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
+
+fn rename_if_exists(from: &Path, to: &Path) -> io::Result<()> {
+    match std::fs::rename(from, to) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
+
+// quotes, backslashes and control characters escaped Apache-style, so a request can't forge
+// extra fields or lines
+fn escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => escaped.push_str(&format!("\\x{:02x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// a quoted JSON string
+fn json_string(value: &str) -> String {
+    let mut json = String::with_capacity(value.len() + 2);
+    json.push('"');
+    for c in value.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            '\r' => json.push_str("\\r"),
+            '\t' => json.push_str("\\t"),
+            c if c.is_control() => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c),
+        }
+    }
+    json.push('"');
+    json
+}
+
+// 18/Oct/2026:13:55:36 +0000
+fn clf_time(time: SystemTime) -> String {
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    let (year, month, day, hour, minute, second) = utc(time);
+    format!(
+        "{:02}/{}/{}:{:02}:{:02}:{:02} +0000",
+        day,
+        MONTHS[month as usize - 1],
+        year,
+        hour,
+        minute,
+        second
+    )
+}
+
+// 2026-10-18T13:55:36Z
+fn rfc3339(time: SystemTime) -> String {
+    let (year, month, day, hour, minute, second) = utc(time);
+    format!(
+        "{}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year, month, day, hour, minute, second
+    )
+}
+
+// the UTC calendar date and time, using the days-to-civil algorithm from
+// https://howardhinnant.github.io/date_algorithms.html
+fn utc(time: SystemTime) -> (i64, u32, u32, u64, u64, u64) {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO)
+        .as_secs();
+    let (days, rest) = ((seconds / 86_400) as i64, seconds % 86_400);
+
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let day_of_era = z.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    (year, month, day, rest / 3_600, rest % 3_600 / 60, rest % 60)
+}
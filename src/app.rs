@@ -1,34 +1,189 @@
 // src/app.rs
 
 // dependencies
-use crate::handler::Handler;
+use crate::access_log::PendingLogs;
+use crate::body::{RequestBody, ResponseBody};
+#[cfg(feature = "compression")]
+use crate::compression::Compression;
+#[cfg(feature = "websocket")]
+use crate::handler::WsHandler;
+use crate::handler::{Handler, ParamHandler, StreamHandler};
+use crate::log::{log_error, log_info};
 use crate::method::{Method, convert_method};
-use crate::request::AppRequest;
+use crate::middleware::{Layer, Middleware, Next};
+use crate::request::{AppRequest, resolve_client_ip, resolve_host, resolve_scheme};
 use crate::response::AppResponse;
-use crate::router::Router;
-use http_body_util::{BodyExt, Full};
-use hyper::body::Bytes;
+use crate::router::{RouteHandler, Router};
+use crate::static_files::StaticDir;
+#[cfg(feature = "tera")]
+use crate::templates::{Templates, TemplatesHandle};
+use crate::version::convert_version;
+use futures_util::FutureExt;
+use http_body_util::BodyExt;
 use hyper::server::conn::http1;
 use hyper::service::service_fn;
 use hyper::{Request, Response};
 use hyper_util::rt::TokioIo;
 use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::ops::ControlFlow;
+use std::panic::AssertUnwindSafe;
+use std::path::PathBuf;
 use std::sync::Arc;
+#[cfg(feature = "compression")]
+use std::sync::Mutex;
+use std::time::Duration;
 use tokio::net::TcpListener;
 
-
-// struct type to represent an Application, consists of a router
+// struct type to represent an Application, consists of a router, middleware, static file
+// directories and its settings
 #[derive(Debug)]
 pub struct App {
     router: Router,
+    pub(crate) middleware: Vec<Layer>,
+    // middleware of single routes, keyed by the route's method and path
+    pub(crate) route_middleware: HashMap<(Method, String), Vec<Arc<dyn Middleware>>>,
+    // callbacks for path parameters, keyed by the parameter's name
+    params: HashMap<String, Vec<Arc<dyn ParamHandler>>>,
+    static_dirs: Vec<StaticDir>,
+    trust_proxy: bool,
+    #[cfg(feature = "compression")]
+    compression: Option<Compression>,
+    #[cfg(feature = "compression")]
+    decompression_limit: usize,
+    #[cfg(feature = "tera")]
+    templates: Option<Arc<Templates>>,
+    #[cfg(feature = "tera")]
+    template_reload: bool,
 }
 
 // methods for the App type
 impl App {
     pub fn new() -> Self {
         App {
-            router: Router { routes: Vec::new() },
+            router: Router::new(),
+            middleware: Vec::new(),
+            route_middleware: HashMap::new(),
+            params: HashMap::new(),
+            static_dirs: Vec::new(),
+            trust_proxy: false,
+            #[cfg(feature = "compression")]
+            compression: None,
+            #[cfg(feature = "compression")]
+            decompression_limit: 10 * 1024 * 1024,
+            #[cfg(feature = "tera")]
+            templates: None,
+            #[cfg(feature = "tera")]
+            template_reload: false,
+        }
+    }
+
+    // trust X-Forwarded-* and Forwarded headers when working out the request scheme and host,
+    // only enable this when the app sits behind a proxy that sets (or strips) those headers
+    pub fn trust_proxy(&mut self, trust: bool) -> &mut Self {
+        self.trust_proxy = trust;
+        self
+    }
+
+    // compress response bodies for clients which send a matching Accept-Encoding,
+    // e.g. app.compression(Compression::new().min_size(2048))
+    #[cfg(feature = "compression")]
+    pub fn compression(&mut self, compression: Compression) -> &mut Self {
+        self.compression = Some(compression);
+        self
+    }
+
+    // the largest a compressed request body may grow to when it's decoded, 10 MiB by default,
+    // bigger ones get a 413 instead of filling the memory
+    #[cfg(feature = "compression")]
+    pub fn decompression_limit(&mut self, bytes: usize) -> &mut Self {
+        self.decompression_limit = bytes;
+        self
+    }
+
+    // load the Tera templates in dir (and its subdirectories) for AppRequest::render,
+    // e.g. app.templates("templates")? makes templates/admin/users.html "admin/users.html"
+    #[cfg(feature = "tera")]
+    pub fn templates(&mut self, dir: impl Into<PathBuf>) -> Result<&mut Self, tera::Error> {
+        self.templates = Some(Arc::new(Templates::load(dir, self.template_reload)?));
+        Ok(self)
+    }
+
+    // reload the templates whenever one of them changes, handy in development but it checks
+    // the directory on every render, so leave it off in production
+    #[cfg(feature = "tera")]
+    pub fn template_reload(&mut self, reload: bool) -> &mut Self {
+        // kept for templates loaded later, and applied to any already loaded, which nothing
+        // else holds until the app is listening
+        self.template_reload = reload;
+        if let Some(templates) = self.templates.as_mut().and_then(Arc::get_mut) {
+            templates.set_reload(reload);
+        }
+        self
+    }
+
+    // run middleware around every request, in the order they were added, the first one added
+    // sees the request first and the response last, e.g.
+    // app.use_middleware(|req, next: Next| async move { next.run(req).await })
+    pub fn use_middleware(&mut self, middleware: impl Middleware + 'static) -> &mut Self {
+        self.middleware.push(Layer::new("/", Arc::new(middleware)));
+        self
+    }
+
+    // run middleware around requests under prefix only, e.g. app.use_at("/admin", auth) covers
+    // /admin and /admin/users but not /administrator, it keeps its place among use_middleware
+    pub fn use_at(&mut self, prefix: &str, middleware: impl Middleware + 'static) -> &mut Self {
+        self.middleware
+            .push(Layer::new(prefix, Arc::new(middleware)));
+        self
+    }
+
+    // run middleware around the route for method and path, its pattern as it was added (e.g.
+    // "/users/:id"), after the App's middleware and once the route is found, e.g.
+    // app.use_for_route(Method::Post, "/items", limit), requests which match no route never
+    // reach it
+    pub fn use_for_route(
+        &mut self,
+        method: Method,
+        path: &str,
+        middleware: impl Middleware + 'static,
+    ) -> &mut Self {
+        self.route_middleware
+            .entry((method, path.to_string()))
+            .or_default()
+            .push(Arc::new(middleware));
+        self
+    }
+
+    // add the routes of router under prefix, e.g. its "/users" mounted at "/api" serves
+    // /api/users, its middleware joins the app's as use_at(prefix, ..) layers in the order
+    // they were added, so they run after the app middleware added before the mount and before
+    // any use_for_route middleware, which names mounted routes by their full path
+    pub fn mount(&mut self, prefix: &str, router: Router) -> &mut Self {
+        let prefix = prefix.trim_end_matches('/');
+        for middleware in router.middleware {
+            self.middleware.push(Layer::new(prefix, middleware));
+        }
+        for mut route in router.routes {
+            route.path = match route.path.as_str() {
+                "/" if !prefix.is_empty() => prefix.to_string(),
+                path => format!("{}{}", prefix, path),
+            };
+            self.router.routes.push(route);
         }
+        self
+    }
+
+    // run callback whenever the matched route has the path parameter name, before the route's
+    // handler and its steps, e.g. to load the entity into req.extensions or answer with a 404,
+    // app.param("user_id", |req: &mut AppRequest, id: &str| ...) covers "/users/:user_id"
+    // and "/users/:user_id/posts" alike
+    pub fn param(&mut self, name: &str, callback: impl ParamHandler + 'static) -> &mut Self {
+        self.params
+            .entry(name.to_string())
+            .or_default()
+            .push(Arc::new(callback));
+        self
     }
 
     pub fn get(&mut self, path: &str, handler: impl Handler + 'static) -> &mut Self {
@@ -56,32 +211,83 @@ impl App {
         self
     }
 
+    // a POST route whose async handler reads the body itself, e.g. for file uploads
+    pub fn post_stream(&mut self, path: &str, handler: impl StreamHandler + 'static) -> &mut Self {
+        self.router.add_stream_route(Method::Post, path, handler);
+        self
+    }
+
+    // a PUT route whose async handler reads the body itself
+    pub fn put_stream(&mut self, path: &str, handler: impl StreamHandler + 'static) -> &mut Self {
+        self.router.add_stream_route(Method::Put, path, handler);
+        self
+    }
+
+    // a WebSocket endpoint, GET requests to path are upgraded and the connection handed to
+    // the async handler, e.g. app.ws("/socket", |mut socket: WebSocket| async move { ... })
+    #[cfg(feature = "websocket")]
+    pub fn ws(&mut self, path: &str, handler: impl WsHandler + 'static) -> &mut Self {
+        self.router.add_ws_route(path, handler);
+        self
+    }
+
+    // serve the files in dir for GET requests under prefix, e.g. with ("/assets", "./public")
+    // ./public/app.js is served at /assets/app.js, routes take precedence over files
+    pub fn static_dir(&mut self, prefix: &str, dir: impl Into<PathBuf>) -> &mut Self {
+        self.static_dirs.push(StaticDir::new(prefix, dir));
+        self
+    }
+
+    // the method and path pattern of the route the request matches, when that route has
+    // middleware of its own
+    pub(crate) fn route_middleware_key(&self, req: &AppRequest) -> Option<(Method, String)> {
+        let route = self.router.find(&req.method, &req.path)?;
+        let key = (route.method.clone(), route.path.clone());
+        self.route_middleware.contains_key(&key).then_some(key)
+    }
+
     pub async fn listen(self, port: u16) {
-        println!("Server listening on port {}", port);
+        log_info!("Server listening on port {}", port);
 
-        let router = Arc::new(self.router);
+        let app = Arc::new(self);
 
         let listener = TcpListener::bind(format!("127.0.0.1:{}", port))
             .await
             .unwrap();
 
         loop {
-            let (socket, _remote_addr) = listener.accept().await.unwrap();
-            let router = router.clone();
+            let (socket, remote_addr) = match listener.accept().await {
+                Ok(accepted) => accepted,
+                Err(err) => {
+                    // e.g. out of file descriptors, back off instead of spinning
+                    log_error!("failed to accept connection: {}", err);
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    continue;
+                }
+            };
+            let app = app.clone();
 
             tokio::task::spawn(async move {
                 let io = TokioIo::new(socket);
 
-                if let Err(err) = http1::Builder::new()
+                let connection = http1::Builder::new()
                     .serve_connection(
                         io,
                         service_fn(move |req| {
-                            handle_request(req, router.clone()) // Pass router
+                            serve_request(req, app.clone(), remote_addr) // Pass app
                         }),
                     )
-                    .await
-                {
-                    println!("Error: {:?}", err);
+                    // keep the connection usable after a 101 response, for WebSockets
+                    .with_upgrades();
+
+                #[cfg(feature = "tracing")]
+                let connection = tracing::Instrument::instrument(
+                    connection,
+                    tracing::info_span!("connection", remote_addr = %remote_addr),
+                );
+
+                if let Err(err) = connection.await {
+                    log_error!("connection from {} failed: {}", remote_addr, err);
                 }
             });
         }
@@ -95,10 +301,70 @@ impl Default for App {
     }
 }
 
+// handle a request, a handler which panics is reported and answered with a 500 instead of
+// dropping the connection, with the tracing feature each request gets a span with its method,
+// path, route, status and latency
+async fn serve_request(
+    hyper_req: Request<hyper::body::Incoming>,
+    app: Arc<App>,
+    remote_addr: SocketAddr,
+) -> Result<Response<ResponseBody>, hyper::Error> {
+    #[cfg(feature = "tracing")]
+    let (span, started) = (
+        tracing::info_span!(
+            "request",
+            method = %hyper_req.method(),
+            path = %hyper_req.uri().path(),
+            route = tracing::field::Empty,
+            status = tracing::field::Empty,
+            latency_ms = tracing::field::Empty,
+        ),
+        std::time::Instant::now(),
+    );
+
+    let path = hyper_req.uri().path().to_string();
+    // outside the guarded future, so a panicking request is still logged with its 500
+    let pending_logs = PendingLogs::default();
+    let response = AssertUnwindSafe(handle_request(
+        hyper_req,
+        app,
+        remote_addr,
+        pending_logs.clone(),
+    ))
+    .catch_unwind();
+
+    #[cfg(feature = "tracing")]
+    let response = tracing::Instrument::instrument(response, span.clone());
+
+    let result = response.await.unwrap_or_else(|panic| {
+        let message = panic
+            .downcast_ref::<&str>()
+            .copied()
+            .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+            .unwrap_or("unknown panic");
+        log_error!("handler for {} panicked: {}", path, message);
+        Ok(fallback_response())
+    });
+    let result = result.map(|response| pending_logs.finish(response));
+
+    #[cfg(feature = "tracing")]
+    {
+        if let Ok(response) = &result {
+            span.record("status", response.status().as_u16());
+        }
+        span.record("latency_ms", started.elapsed().as_secs_f64() * 1000.0);
+        tracing::info!(parent: &span, "finished request");
+    }
+
+    result
+}
+
 async fn handle_request(
     hyper_req: Request<hyper::body::Incoming>,
-    router: Arc<Router>,
-) -> Result<Response<Full<Bytes>>, hyper::Error> {
+    app: Arc<App>,
+    remote_addr: SocketAddr,
+    pending_logs: PendingLogs,
+) -> Result<Response<ResponseBody>, hyper::Error> {
     let (parts, body) = hyper_req.into_parts();
 
     let method = convert_method(&parts.method);
@@ -110,30 +376,216 @@ async fn handle_request(
         .map(|(name, value)| (name.to_string(), value.to_str().unwrap_or("").to_string()))
         .collect();
 
-    let body_bytes = body.collect().await?.to_bytes().to_vec();
+    let client_ip = resolve_client_ip(remote_addr, &headers, app.trust_proxy);
+
+    // streaming routes read the body themselves, everything else gets it collected up front
+    // so middleware can see it too
+    let (body_bytes, body_stream) = match app.router.find_route(&method, path) {
+        Some(RouteHandler::Streaming(_)) => (Vec::new(), Some(RequestBody::incoming(body))),
+        _ => (body.collect().await?.to_bytes().to_vec(), None),
+    };
+
+    // compressed bodies are decoded before the handler sees them, streaming routes read them as
+    // they were sent, a body which can't be decoded is answered at the end of the middleware
+    // chain, so middleware such as AccessLog still see the request
+    #[cfg(feature = "compression")]
+    let mut headers = headers;
+    #[cfg(feature = "compression")]
+    let (body_bytes, rejection) =
+        match decompress_body(&mut headers, body_bytes, app.decompression_limit) {
+            Ok(body) => (body, None),
+            Err(err) => (Vec::new(), Some(AppResponse::from(err))),
+        };
+
+    // kept for answering conditional and Range requests after the handler has taken the request
+    let get_headers: HashMap<String, String> = headers
+        .iter()
+        .filter(|(name, _)| {
+            matches!(
+                name.as_str(),
+                "range" | "if-range" | "if-none-match" | "if-modified-since"
+            )
+        })
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect();
+
+    #[cfg(feature = "compression")]
+    let accept_encoding = headers.get("accept-encoding").cloned();
 
-    let app_req = AppRequest {
+    let scheme = resolve_scheme(&parts.uri, &headers, app.trust_proxy);
+    let host = resolve_host(&parts.uri, &headers, app.trust_proxy);
+
+    let mut app_req = AppRequest {
         method: method.clone(),
+        uri: parts.uri.to_string(),
+        scheme,
+        host,
+        version: convert_version(parts.version),
+        remote_addr,
+        client_ip,
         headers,
         path: path.to_string(),
+        params: HashMap::new(),
         body: body_bytes,
+        extensions: parts.extensions,
+        body_stream,
     };
 
-    let response = if let Some(handler) = router.find_route(&method, path) {
-        handler.handle(&app_req)
+    app_req.extensions.insert(pending_logs);
+    #[cfg(feature = "compression")]
+    if let Some(response) = rejection {
+        app_req
+            .extensions
+            .insert(Rejection(Arc::new(Mutex::new(Some(response)))));
+    }
+
+    // handlers render templates with req.render()
+    #[cfg(feature = "tera")]
+    if let Some(templates) = &app.templates {
+        app_req
+            .extensions
+            .insert(TemplatesHandle(templates.clone()));
+    }
+
+    // the middleware chain ends in dispatch, which finds the route
+    let response = Next::new(app.clone()).run(app_req).await;
+
+    // GET responses can be revalidated with a 304, and buffered ones fetched in parts
+    let response = if method == Method::Get {
+        let response = crate::conditional::apply_to_get(&get_headers, response);
+        crate::range::apply_to_bytes(&get_headers, response)
     } else {
-        let mut headers = HashMap::new();
-        headers.insert("Content-Type".to_string(), "text/plain".to_string());
+        response
+    };
 
-        AppResponse::new(404, "Not Found").with_header("Content-Type", "text/plain")
+    #[cfg(feature = "compression")]
+    let response = match &app.compression {
+        Some(compression) => compression.apply(accept_encoding.as_deref(), response),
+        None => response,
     };
 
-    let response_builder = response.headers.iter().fold(
-        Response::builder().status(response.status),
-        |builder, (key, value)| builder.header(key, value),
-    );
+    Ok(into_hyper(response, path))
+}
+
+// struct type to represent the answer to a request which was refused before the middleware
+// chain ran, such as a body that couldn't be decoded, dispatch sends it instead of routing
+#[cfg(feature = "compression")]
+#[derive(Clone)]
+struct Rejection(Arc<Mutex<Option<AppResponse>>>);
+
+// run the route matching the request, as middleware may have left it, falling back to static
+// files and then a 404
+pub(crate) async fn dispatch(app: Arc<App>, mut req: AppRequest) -> AppResponse {
+    #[cfg(feature = "compression")]
+    if let Some(response) = req
+        .extensions
+        .remove::<Rejection>()
+        .and_then(|rejection| rejection.0.lock().unwrap().take())
+    {
+        return response;
+    }
+
+    let route = app.router.find(&req.method, &req.path);
 
-    let body = response.body.unwrap_or_default();
-    let body = Full::new(Bytes::from(body));
-    Ok(response_builder.body(body).unwrap())
+    #[cfg(feature = "tracing")]
+    if let Some(route) = route {
+        tracing::Span::current().record("route", route.path.as_str());
+    }
+
+    // param callbacks run in the order the parameters appear in the route's path
+    if let Some(route) = route {
+        req.params = route.params(&req.path).unwrap_or_default();
+        for name in route.param_names() {
+            let value = req.params.get(name).cloned().unwrap_or_default();
+            for callback in app.params.get(name).into_iter().flatten() {
+                if let ControlFlow::Break(response) = callback.run(&mut req, &value) {
+                    return response;
+                }
+            }
+        }
+    }
+
+    match route.map(|route| &route.handler) {
+        Some(RouteHandler::Buffered(handler)) => {
+            // middleware rewrote the path of a streaming route to a buffered one
+            if let Some(stream) = req.body_stream.take() {
+                match stream.collect(usize::MAX).await {
+                    Ok(body) => req.body = body,
+                    Err(err) => {
+                        return AppResponse::bad_request(err.to_string())
+                            .with_header("Content-Type", "text/plain");
+                    }
+                }
+            }
+            handler.handle_mut(&mut req)
+        }
+        Some(RouteHandler::Streaming(handler)) => handler.handle(req).await,
+        #[cfg(feature = "websocket")]
+        Some(RouteHandler::WebSocket(handler)) => {
+            let on_upgrade = req.extensions.remove::<hyper::upgrade::OnUpgrade>();
+            crate::websocket::upgrade(handler.clone(), req, on_upgrade)
+        }
+        None => match serve_static(&app, &req.method, &req.path, &req.headers).await {
+            Some(response) => response,
+            None => AppResponse::new(404, "Not Found").with_header("Content-Type", "text/plain"),
+        },
+    }
+}
+
+// a handler that set an invalid status or header gets a 500 rather than a dropped connection
+fn into_hyper(response: AppResponse, path: &str) -> Response<ResponseBody> {
+    response.into_hyper().unwrap_or_else(|err| {
+        log_error!("failed to send response for {}: {}", path, err);
+        fallback_response()
+    })
+}
+
+// decode a body sent with a Content-Encoding, the headers then describe the decoded body
+#[cfg(feature = "compression")]
+fn decompress_body(
+    headers: &mut HashMap<String, String>,
+    body: Vec<u8>,
+    limit: usize,
+) -> Result<Vec<u8>, crate::compression::DecompressionError> {
+    let Some(encoding) = headers.get("content-encoding").cloned() else {
+        return Ok(body);
+    };
+    if body.is_empty() {
+        return Ok(body);
+    }
+
+    let body = crate::compression::decompress(&encoding, body, limit)?;
+    headers.remove("content-encoding");
+    headers.insert("content-length".to_string(), body.len().to_string());
+    Ok(body)
+}
+
+// the first static directory with a file for this path, only GET (and HEAD) requests are served
+async fn serve_static(
+    app: &App,
+    method: &Method,
+    path: &str,
+    headers: &HashMap<String, String>,
+) -> Option<AppResponse> {
+    if *method != Method::Get {
+        return None;
+    }
+
+    for dir in &app.static_dirs {
+        if let Some(response) = dir.serve(path, headers).await {
+            return Some(response);
+        }
+    }
+    None
+}
+
+// the plain 500 sent when a handler's response can't be converted
+fn fallback_response() -> Response<ResponseBody> {
+    let mut response = Response::new(ResponseBody::Bytes(b"Internal Server Error".to_vec()));
+    *response.status_mut() = hyper::StatusCode::INTERNAL_SERVER_ERROR;
+    response.headers_mut().insert(
+        hyper::header::CONTENT_TYPE,
+        hyper::header::HeaderValue::from_static("text/plain"),
+    );
+    response
 }
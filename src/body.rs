@@ -0,0 +1,159 @@
+// src/body.rs
+
+// dependencies
+use futures_core::Stream;
+use http_body_util::BodyExt;
+use hyper::body::{Body, Bytes, Frame, Incoming, SizeHint};
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+// a boxed stream of body chunks, e.g. a tokio_util::io::ReaderStream over a file
+pub type ByteStream = Pin<Box<dyn Stream<Item = io::Result<Bytes>> + Send>>;
+
+// struct type to represent a request body which is read chunk by chunk as it arrives,
+// instead of being collected into AppRequest::body before the handler runs
+pub struct RequestBody {
+    inner: Inner,
+}
+
+// where the chunks come from, the connection or a body that was already collected
+enum Inner {
+    Incoming(Incoming),
+    Buffered(Option<Bytes>),
+}
+
+// methods for the RequestBody type
+impl RequestBody {
+    pub(crate) fn incoming(body: Incoming) -> Self {
+        RequestBody {
+            inner: Inner::Incoming(body),
+        }
+    }
+
+    pub(crate) fn buffered(body: Vec<u8>) -> Self {
+        RequestBody {
+            inner: Inner::Buffered(Some(Bytes::from(body))),
+        }
+    }
+
+    // the next chunk of data, None once the body is finished
+    pub async fn chunk(&mut self) -> io::Result<Option<Bytes>> {
+        match &mut self.inner {
+            Inner::Incoming(body) => {
+                // skip over trailers, only data frames are interesting here
+                while let Some(frame) = body.frame().await {
+                    let frame = frame.map_err(io::Error::other)?;
+                    if let Ok(data) = frame.into_data()
+                        && !data.is_empty()
+                    {
+                        return Ok(Some(data));
+                    }
+                }
+                Ok(None)
+            }
+            Inner::Buffered(data) => Ok(data.take().filter(|data| !data.is_empty())),
+        }
+    }
+
+    // read the rest of the body into memory, failing once more than limit bytes have arrived
+    pub async fn collect(mut self, limit: usize) -> io::Result<Vec<u8>> {
+        let mut collected = Vec::new();
+
+        while let Some(chunk) = self.chunk().await? {
+            if collected.len() + chunk.len() > limit {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("request body is larger than {} bytes", limit),
+                ));
+            }
+            collected.extend_from_slice(&chunk);
+        }
+
+        Ok(collected)
+    }
+}
+
+// implement the Debug trait for the RequestBody type
+impl std::fmt::Debug for RequestBody {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let kind = match self.inner {
+            Inner::Incoming(_) => "incoming",
+            Inner::Buffered(_) => "buffered",
+        };
+        f.debug_struct("RequestBody").field("kind", &kind).finish()
+    }
+}
+
+// enum type to represent the body of a response, buffered bodies are sent in one go with a
+// Content-Length, streamed bodies are sent chunk by chunk with chunked encoding as they're produced
+pub enum ResponseBody {
+    Empty,
+    Bytes(Vec<u8>),
+    Stream(ByteStream),
+}
+
+// methods for the ResponseBody type
+impl ResponseBody {
+    pub fn stream(stream: impl Stream<Item = io::Result<Bytes>> + Send + 'static) -> Self {
+        ResponseBody::Stream(Box::pin(stream))
+    }
+
+    // the buffered bytes, None for streamed bodies
+    pub fn as_bytes(&self) -> Option<&[u8]> {
+        match self {
+            ResponseBody::Empty => Some(&[]),
+            ResponseBody::Bytes(bytes) => Some(bytes),
+            ResponseBody::Stream(_) => None,
+        }
+    }
+}
+
+// implement the Debug trait for the ResponseBody type
+impl std::fmt::Debug for ResponseBody {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ResponseBody::Empty => f.write_str("Empty"),
+            ResponseBody::Bytes(bytes) => f.debug_tuple("Bytes").field(&bytes.len()).finish(),
+            ResponseBody::Stream(_) => f.write_str("Stream"),
+        }
+    }
+}
+
+// implement hyper's Body trait for the ResponseBody type so it can be handed to hyper directly
+impl Body for ResponseBody {
+    type Data = Bytes;
+    type Error = io::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, io::Error>>> {
+        let this = self.get_mut();
+        match this {
+            ResponseBody::Empty => Poll::Ready(None),
+            ResponseBody::Bytes(bytes) => {
+                let bytes = std::mem::take(bytes);
+                *this = ResponseBody::Empty;
+                Poll::Ready((!bytes.is_empty()).then(|| Ok(Frame::data(Bytes::from(bytes)))))
+            }
+            ResponseBody::Stream(stream) => stream
+                .as_mut()
+                .poll_next(cx)
+                .map(|chunk| chunk.map(|chunk| chunk.map(Frame::data))),
+        }
+    }
+
+    fn is_end_stream(&self) -> bool {
+        matches!(self, ResponseBody::Empty)
+    }
+
+    fn size_hint(&self) -> SizeHint {
+        match self {
+            ResponseBody::Empty => SizeHint::with_exact(0),
+            ResponseBody::Bytes(bytes) => SizeHint::with_exact(bytes.len() as u64),
+            // the length of a stream isn't known up front, so hyper falls back to chunked encoding
+            ResponseBody::Stream(_) => SizeHint::default(),
+        }
+    }
+}
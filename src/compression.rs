@@ -0,0 +1,435 @@
+// src/compression.rs
+
+// dependencies
+use crate::body::{ByteStream, ResponseBody};
+use crate::response::AppResponse;
+use brotli::CompressorWriter;
+use flate2::Compression as Level;
+use flate2::write::{GzEncoder, ZlibEncoder};
+use futures_core::Stream;
+use hyper::body::Bytes;
+use std::io::{self, Read, Write};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+// brotli settings fast enough to compress on every request, 11 is far too slow for that
+const BROTLI_QUALITY: u32 = 5;
+const BROTLI_WINDOW: u32 = 22;
+const BROTLI_BUFFER: usize = 4096;
+
+// struct type to represent the response compression settings, see App::compression
+#[derive(Debug, Clone)]
+pub struct Compression {
+    min_size: usize,
+    content_types: Vec<String>,
+}
+
+// methods for the Compression type
+impl Compression {
+    // compress text (apart from event streams), JSON, JavaScript, XML and SVG bodies of 1 KiB
+    // or more
+    pub fn new() -> Self {
+        Compression {
+            min_size: 1024,
+            content_types: [
+                "text/*",
+                "application/json",
+                "application/*+json",
+                "application/javascript",
+                "application/xml",
+                "application/*+xml",
+                "application/wasm",
+                "image/svg+xml",
+            ]
+            .iter()
+            .map(|content_type| content_type.to_string())
+            .collect(),
+        }
+    }
+
+    // smaller buffered bodies aren't worth compressing, streamed bodies are checked against
+    // their Content-Length when they have one
+    pub fn min_size(mut self, bytes: usize) -> Self {
+        self.min_size = bytes;
+        self
+    }
+
+    // replace the media types which are compressed, "type/*" and "type/*+suffix" are wildcards
+    pub fn content_types(mut self, content_types: &[&str]) -> Self {
+        self.content_types = content_types
+            .iter()
+            .map(|content_type| content_type.to_ascii_lowercase())
+            .collect();
+        self
+    }
+
+    // compress a response for a client which sent accept_encoding, when it's worthwhile
+    pub(crate) fn apply(
+        &self,
+        accept_encoding: Option<&str>,
+        response: AppResponse,
+    ) -> AppResponse {
+        if !self.is_compressible(&response) {
+            return response;
+        }
+
+        // the response depends on Accept-Encoding now, whether or not this client gets it
+        // compressed, so caches must keep the variants apart
+        let response = response.add_vary("Accept-Encoding");
+
+        let Some(encoding) = accept_encoding.and_then(choose_encoding) else {
+            return response;
+        };
+
+        let mut response = response;
+        let body = std::mem::replace(&mut response.body, ResponseBody::Empty);
+        response.body = match body {
+            ResponseBody::Bytes(bytes) => match compress(encoding, &bytes) {
+                Ok(compressed) => ResponseBody::Bytes(compressed),
+                Err(_) => {
+                    response.body = ResponseBody::Bytes(bytes);
+                    return response;
+                }
+            },
+            ResponseBody::Stream(stream) => ResponseBody::stream(CompressStream {
+                inner: stream,
+                encoder: Some(Encoder::new(encoding)),
+            }),
+            ResponseBody::Empty => ResponseBody::Empty,
+        };
+
+        // the length and byte ranges of the original body no longer apply, and the compressed
+        // body is a different representation, so a strong ETag can only be kept as a weak one
+        response.headers.retain(|key, _| {
+            !key.eq_ignore_ascii_case("content-length")
+                && !key.eq_ignore_ascii_case("accept-ranges")
+        });
+        if let Some(etag) = response.header("etag").map(|etag| etag.to_string())
+            && !etag.starts_with("W/")
+        {
+            response = response.with_header("ETag", &format!("W/{}", etag));
+        }
+
+        response.with_header("Content-Encoding", encoding.as_str())
+    }
+
+    // a 200-ish response with an allowed media type, big enough and not already encoded
+    fn is_compressible(&self, response: &AppResponse) -> bool {
+        let status = response.status.as_u16();
+        if !(200..300).contains(&status) || status == 204 || status == 206 {
+            return false;
+        }
+        if response.header("content-encoding").is_some() {
+            return false;
+        }
+
+        let size = match &response.body {
+            ResponseBody::Empty => return false,
+            ResponseBody::Bytes(bytes) => Some(bytes.len()),
+            ResponseBody::Stream(_) => response
+                .header("content-length")
+                .and_then(|len| len.trim().parse().ok()),
+        };
+        if size.is_some_and(|size| size < self.min_size) {
+            return false;
+        }
+
+        let Some(content_type) = response.header("content-type") else {
+            return false;
+        };
+        let media_type = content_type
+            .split(';')
+            .next()
+            .unwrap_or("")
+            .trim()
+            .to_ascii_lowercase();
+
+        // an encoder (and many proxies in front of one) holds events back until it has enough
+        // to compress, so event streams are only compressed when listed by name, not by text/*
+        if media_type == "text/event-stream" {
+            return self
+                .content_types
+                .iter()
+                .any(|allowed| allowed == "text/event-stream");
+        }
+
+        self.content_types
+            .iter()
+            .any(|allowed| media_type_matches(allowed, &media_type))
+    }
+}
+
+// implement the Default trait for the Compression type
+impl Default for Compression {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// enum type to represent a supported content coding
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Encoding {
+    Brotli,
+    Gzip,
+    Deflate,
+}
+
+// methods for the Encoding type
+impl Encoding {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Encoding::Brotli => "br",
+            Encoding::Gzip => "gzip",
+            Encoding::Deflate => "deflate",
+        }
+    }
+}
+
+// the coding the client prefers by q-value, ties go to the best compression (br, gzip, deflate)
+fn choose_encoding(accept_encoding: &str) -> Option<Encoding> {
+    let mut wildcard = None;
+    let mut listed = Vec::new();
+
+    for item in accept_encoding.split(',') {
+        let mut params = item.split(';');
+        let coding = params.next().unwrap_or("").trim().to_ascii_lowercase();
+        let q = params
+            .filter_map(|param| param.split_once('='))
+            .find(|(key, _)| key.trim().eq_ignore_ascii_case("q"))
+            .and_then(|(_, value)| value.trim().parse::<f32>().ok())
+            .unwrap_or(1.0);
+
+        if coding == "*" {
+            wildcard = Some(q);
+        } else {
+            listed.push((coding, q));
+        }
+    }
+
+    [Encoding::Brotli, Encoding::Gzip, Encoding::Deflate]
+        .into_iter()
+        .filter_map(|encoding| {
+            let q = listed
+                .iter()
+                .find(|(coding, _)| {
+                    coding == encoding.as_str()
+                        || (encoding == Encoding::Gzip && coding == "x-gzip")
+                })
+                .map(|(_, q)| *q)
+                .or(wildcard)?;
+            (q > 0.0).then_some((encoding, q))
+        })
+        // max_by keeps the last of equal items, so compare in reverse preference order
+        .rev()
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(encoding, _)| encoding)
+}
+
+// compress a whole buffered body
+fn compress(encoding: Encoding, bytes: &[u8]) -> io::Result<Vec<u8>> {
+    let mut encoder = Encoder::new(encoding);
+    let mut compressed = encoder.write(bytes)?;
+    compressed.extend(encoder.finish()?);
+    Ok(compressed)
+}
+
+// enum type to represent a compressor writing into a buffer which is drained after every chunk
+enum Encoder {
+    Brotli(Box<CompressorWriter<Vec<u8>>>),
+    Gzip(GzEncoder<Vec<u8>>),
+    Deflate(ZlibEncoder<Vec<u8>>),
+}
+
+// methods for the Encoder type
+impl Encoder {
+    fn new(encoding: Encoding) -> Self {
+        match encoding {
+            Encoding::Brotli => Encoder::Brotli(Box::new(CompressorWriter::new(
+                Vec::new(),
+                BROTLI_BUFFER,
+                BROTLI_QUALITY,
+                BROTLI_WINDOW,
+            ))),
+            Encoding::Gzip => Encoder::Gzip(GzEncoder::new(Vec::new(), Level::default())),
+            // the "deflate" content coding is the zlib format, not raw deflate
+            Encoding::Deflate => Encoder::Deflate(ZlibEncoder::new(Vec::new(), Level::default())),
+        }
+    }
+
+    // compress a chunk and flush, so the client can decode everything sent so far
+    fn write(&mut self, chunk: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            Encoder::Brotli(encoder) => {
+                encoder.write_all(chunk)?;
+                encoder.flush()?;
+                Ok(std::mem::take(encoder.get_mut()))
+            }
+            Encoder::Gzip(encoder) => {
+                encoder.write_all(chunk)?;
+                encoder.flush()?;
+                Ok(std::mem::take(encoder.get_mut()))
+            }
+            Encoder::Deflate(encoder) => {
+                encoder.write_all(chunk)?;
+                encoder.flush()?;
+                Ok(std::mem::take(encoder.get_mut()))
+            }
+        }
+    }
+
+    // the end of the compressed stream
+    fn finish(self) -> io::Result<Vec<u8>> {
+        match self {
+            Encoder::Brotli(encoder) => Ok(encoder.into_inner()),
+            Encoder::Gzip(encoder) => encoder.finish(),
+            Encoder::Deflate(encoder) => encoder.finish(),
+        }
+    }
+}
+
+// struct type to represent a streamed body compressed chunk by chunk as it's sent
+struct CompressStream {
+    inner: ByteStream,
+    // None once the compressed stream has been finished
+    encoder: Option<Encoder>,
+}
+
+// implement the Stream trait for the CompressStream type
+impl Stream for CompressStream {
+    type Item = io::Result<Bytes>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        loop {
+            let Some(encoder) = this.encoder.as_mut() else {
+                return Poll::Ready(None);
+            };
+
+            match this.inner.as_mut().poll_next(cx) {
+                Poll::Ready(Some(Ok(chunk))) => match encoder.write(&chunk) {
+                    // a chunk can disappear into the compressor's window
+                    Ok(compressed) if compressed.is_empty() => continue,
+                    Ok(compressed) => return Poll::Ready(Some(Ok(Bytes::from(compressed)))),
+                    Err(err) => {
+                        this.encoder = None;
+                        return Poll::Ready(Some(Err(err)));
+                    }
+                },
+                Poll::Ready(Some(Err(err))) => {
+                    this.encoder = None;
+                    return Poll::Ready(Some(Err(err)));
+                }
+                Poll::Ready(None) => {
+                    let encoder = this.encoder.take().unwrap();
+                    return Poll::Ready(Some(encoder.finish().map(Bytes::from)));
+                }
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+// whether an allowlist entry like "text/*" or "application/*+json" covers a media type
+fn media_type_matches(allowed: &str, media_type: &str) -> bool {
+    let (Some((allowed_kind, allowed_subtype)), Some((kind, subtype))) =
+        (allowed.split_once('/'), media_type.split_once('/'))
+    else {
+        return false;
+    };
+
+    if allowed_kind != "*" && allowed_kind != kind {
+        return false;
+    }
+
+    match allowed_subtype.strip_prefix('*') {
+        Some("") => true,
+        Some(suffix) => subtype.ends_with(suffix),
+        None => allowed_subtype == subtype,
+    }
+}
+
+// enum type to represent a request body which couldn't be decompressed
+#[derive(Debug)]
+pub enum DecompressionError {
+    // a Content-Encoding other than gzip, deflate, br or identity
+    Unsupported(String),
+    // the decompressed body would be larger than the limit, e.g. a zip bomb
+    TooLarge { limit: usize },
+    // the body isn't valid for its Content-Encoding
+    Invalid(io::Error),
+}
+
+// implement the Display trait for the DecompressionError type
+impl std::fmt::Display for DecompressionError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecompressionError::Unsupported(encoding) => {
+                write!(f, "unsupported content encoding {:?}", encoding)
+            }
+            DecompressionError::TooLarge { limit } => {
+                write!(f, "decompressed body is larger than {} bytes", limit)
+            }
+            DecompressionError::Invalid(err) => write!(f, "invalid compressed body: {}", err),
+        }
+    }
+}
+
+// implement the Error trait for the DecompressionError type
+impl std::error::Error for DecompressionError {}
+
+// implement the From trait to answer a DecompressionError with 415, 413 or 400
+impl From<DecompressionError> for AppResponse {
+    fn from(err: DecompressionError) -> Self {
+        let status = match err {
+            DecompressionError::Unsupported(_) => 415,
+            DecompressionError::TooLarge { .. } => 413,
+            DecompressionError::Invalid(_) => 400,
+        };
+        let response =
+            AppResponse::new(status, err.to_string()).with_header("Content-Type", "text/plain");
+
+        // tell the client which encodings it can use instead
+        match err {
+            DecompressionError::Unsupported(_) => {
+                response.with_header("Accept-Encoding", "gzip, deflate, br")
+            }
+            _ => response,
+        }
+    }
+}
+
+// undo a request's Content-Encoding (codings applied in order, so removed in reverse), failing
+// once the result grows past limit bytes
+pub(crate) fn decompress(
+    content_encoding: &str,
+    body: Vec<u8>,
+    limit: usize,
+) -> Result<Vec<u8>, DecompressionError> {
+    let mut body = body;
+
+    for coding in content_encoding.rsplit(',').map(|coding| coding.trim()) {
+        let coding = coding.to_ascii_lowercase();
+        let decoder: Box<dyn io::Read + '_> = match coding.as_str() {
+            "" | "identity" => continue,
+            "gzip" | "x-gzip" => Box::new(flate2::read::MultiGzDecoder::new(&body[..])),
+            "deflate" => Box::new(flate2::read::ZlibDecoder::new(&body[..])),
+            "br" => Box::new(brotli::Decompressor::new(&body[..], BROTLI_BUFFER)),
+            _ => return Err(DecompressionError::Unsupported(coding)),
+        };
+
+        // read one byte past the limit to tell a body of exactly limit bytes from a bigger one
+        let mut decoded = Vec::new();
+        decoder
+            .take(limit as u64 + 1)
+            .read_to_end(&mut decoded)
+            .map_err(DecompressionError::Invalid)?;
+        if decoded.len() > limit {
+            return Err(DecompressionError::TooLarge { limit });
+        }
+
+        body = decoded;
+    }
+
+    Ok(body)
+}
@@ -0,0 +1,193 @@
+// src/conditional.rs
+
+// dependencies
+use crate::body::ResponseBody;
+use crate::request::AppRequest;
+use crate::response::AppResponse;
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// headers which still describe the resource in a 304, everything about the body is dropped
+const NOT_MODIFIED_HEADERS: [&str; 7] = [
+    "etag",
+    "last-modified",
+    "cache-control",
+    "content-location",
+    "date",
+    "expires",
+    "vary",
+];
+
+// struct type to represent a failed If-Match or If-Unmodified-Since check, which is answered
+// with a 412 Precondition Failed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PreconditionFailed;
+
+// implement the Display trait for the PreconditionFailed type
+impl std::fmt::Display for PreconditionFailed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "the resource has changed since the client last saw it")
+    }
+}
+
+// implement the Error trait for the PreconditionFailed type
+impl std::error::Error for PreconditionFailed {}
+
+// implement the From trait so a failed precondition can be returned with .into()
+impl From<PreconditionFailed> for AppResponse {
+    fn from(_: PreconditionFailed) -> Self {
+        AppResponse::new(412, "Precondition Failed").with_header("Content-Type", "text/plain")
+    }
+}
+
+// a strong ETag from the length and 64-bit FNV-1a hash of the content, e.g.
+// "\"1-af63dc4c8601ec8c\"" for b"a". FNV-1a is a fixed, published algorithm, so the same
+// content gets the same ETag across builds, releases and servers, which caches and
+// If-Match checks rely on, it isn't meant to resist deliberate collisions
+pub fn strong_etag(content: &[u8]) -> String {
+    format!("\"{:x}-{:016x}\"", content.len(), fnv1a(content))
+}
+
+// 64-bit FNV-1a, see http://www.isthe.com/chongo/tech/comp/fnv/
+fn fnv1a(content: &[u8]) -> u64 {
+    const OFFSET_BASIS: u64 = 0xcbf2_9ce4_8422_2325;
+    const PRIME: u64 = 0x0000_0100_0000_01b3;
+    content.iter().fold(OFFSET_BASIS, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(PRIME)
+    })
+}
+
+// a weak ETag from a size and modification time, cheap for files which aren't read up front
+pub fn weak_etag(len: u64, modified: SystemTime) -> String {
+    let modified = modified
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or(Duration::ZERO);
+    format!(
+        "W/\"{:x}-{:x}{:08x}\"",
+        len,
+        modified.as_secs(),
+        modified.subsec_nanos()
+    )
+}
+
+// conditional request methods for the AppResponse type
+impl AppResponse {
+    // set a strong ETag computed from the buffered body, so GET requests with a matching
+    // If-None-Match get a 304, streamed bodies are left alone
+    pub fn with_etag(self) -> Self {
+        let etag = match &self.body {
+            ResponseBody::Bytes(bytes) => strong_etag(bytes),
+            ResponseBody::Empty => strong_etag(&[]),
+            ResponseBody::Stream(_) => return self,
+        };
+        self.with_header("ETag", &etag)
+    }
+
+    // set Last-Modified, GET requests with an If-Modified-Since at or after it get a 304
+    pub fn with_last_modified(self, modified: SystemTime) -> Self {
+        self.with_header("Last-Modified", &httpdate::fmt_http_date(modified))
+    }
+}
+
+// conditional request methods for the AppRequest type
+impl AppRequest {
+    // check If-Match and If-Unmodified-Since (and If-None-Match) against the current state of
+    // the resource before changing it, an Err means another client got there first and should
+    // be returned as a 412, pass None for a resource which doesn't exist yet
+    pub fn preconditions(
+        &self,
+        etag: Option<&str>,
+        last_modified: Option<SystemTime>,
+    ) -> Result<(), PreconditionFailed> {
+        if let Some(if_match) = self.headers.get("if-match") {
+            if !etag_list_matches(if_match, etag, true) {
+                return Err(PreconditionFailed);
+            }
+        } else if let (Some(since), Some(modified)) =
+            (self.headers.get("if-unmodified-since"), last_modified)
+            && let Ok(since) = httpdate::parse_http_date(since)
+            && whole_seconds(modified) > whole_seconds(since)
+        {
+            return Err(PreconditionFailed);
+        }
+
+        // e.g. If-None-Match: * to only create a resource which doesn't exist yet
+        if let Some(if_none_match) = self.headers.get("if-none-match")
+            && etag_list_matches(if_none_match, etag, false)
+        {
+            return Err(PreconditionFailed);
+        }
+
+        Ok(())
+    }
+}
+
+// answer a GET with a 304 when the response's ETag or Last-Modified shows the client's cached
+// copy is still fresh, If-None-Match takes precedence over If-Modified-Since
+pub(crate) fn apply_to_get(
+    headers: &HashMap<String, String>,
+    response: AppResponse,
+) -> AppResponse {
+    if response.status != 200 && response.status != 206 {
+        return response;
+    }
+
+    let not_modified = if let Some(if_none_match) = headers.get("if-none-match") {
+        etag_list_matches(if_none_match, response.header("etag"), false)
+    } else if let (Some(since), Some(modified)) = (
+        headers.get("if-modified-since"),
+        response.header("last-modified"),
+    ) {
+        match (
+            httpdate::parse_http_date(since),
+            httpdate::parse_http_date(modified),
+        ) {
+            (Ok(since), Ok(modified)) => modified <= since,
+            _ => false,
+        }
+    } else {
+        false
+    };
+
+    if !not_modified {
+        return response;
+    }
+
+    let mut response = response;
+    response.status = hyper::StatusCode::NOT_MODIFIED;
+    response.body = ResponseBody::Empty;
+    response
+        .headers
+        .retain(|key, _| NOT_MODIFIED_HEADERS.contains(&key.to_ascii_lowercase().as_str()));
+    response
+}
+
+// whether a list of entity tags like "\"a\", W/\"b\"" or "*" matches the current etag, strong
+// comparison (If-Match) needs both to be strong and equal, weak comparison ignores the W/
+fn etag_list_matches(list: &str, etag: Option<&str>, strong: bool) -> bool {
+    let Some(etag) = etag else {
+        return false;
+    };
+    if list.trim() == "*" {
+        return true;
+    }
+    if strong && etag.starts_with("W/") {
+        return false;
+    }
+
+    let opaque = |tag: &str| tag.trim().trim_start_matches("W/").to_string();
+    list.split(',').any(|candidate| {
+        let candidate = candidate.trim();
+        if strong && candidate.starts_with("W/") {
+            return false;
+        }
+        opaque(candidate) == opaque(etag)
+    })
+}
+
+// HTTP dates only have whole seconds, so compare times at that precision
+fn whole_seconds(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_secs())
+        .unwrap_or(0)
+}
@@ -0,0 +1,194 @@
+// src/cookie.rs
+
+// dependencies
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// enum type to represent the SameSite attribute of a cookie
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SameSite {
+    Strict,
+    Lax,
+    None,
+}
+
+// methods for the SameSite type
+impl SameSite {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SameSite::Strict => "Strict",
+            SameSite::Lax => "Lax",
+            SameSite::None => "None",
+        }
+    }
+}
+
+// struct type to represent a cookie sent to the client with a Set-Cookie header
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cookie {
+    pub name: String,
+    pub value: String,
+    pub path: Option<String>,
+    pub domain: Option<String>,
+    pub max_age: Option<Duration>,
+    pub expires: Option<SystemTime>,
+    pub secure: bool,
+    pub http_only: bool,
+    pub same_site: Option<SameSite>,
+}
+
+// methods for the Cookie type
+impl Cookie {
+    pub fn new(name: impl Into<String>, value: impl Into<String>) -> Self {
+        Cookie {
+            name: name.into(),
+            value: value.into(),
+            path: None,
+            domain: None,
+            max_age: None,
+            expires: None,
+            secure: false,
+            http_only: false,
+            same_site: None,
+        }
+    }
+
+    // a cookie which tells the client to delete any cookie with the same name,
+    // the path and domain must match the ones the cookie was originally set with
+    pub fn removal(name: impl Into<String>) -> Self {
+        Cookie {
+            max_age: Some(Duration::ZERO),
+            expires: Some(UNIX_EPOCH),
+            ..Cookie::new(name, "")
+        }
+    }
+
+    pub fn path(mut self, path: impl Into<String>) -> Self {
+        self.path = Some(path.into());
+        self
+    }
+
+    pub fn domain(mut self, domain: impl Into<String>) -> Self {
+        self.domain = Some(domain.into());
+        self
+    }
+
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    pub fn expires(mut self, expires: SystemTime) -> Self {
+        self.expires = Some(expires);
+        self
+    }
+
+    pub fn secure(mut self) -> Self {
+        self.secure = true;
+        self
+    }
+
+    pub fn http_only(mut self) -> Self {
+        self.http_only = true;
+        self
+    }
+
+    pub fn same_site(mut self, same_site: SameSite) -> Self {
+        self.same_site = Some(same_site);
+        self
+    }
+
+    // whether the cookie can be sent as it stands: the name must be a token and the value
+    // RFC 6265 cookie-octets (optionally in double quotes), and the path and domain can't hold
+    // ';' or control characters, so none of them can add attributes of their own, e.g.
+    // Cookie::new("pref", "dark; Domain=evil.example") isn't valid
+    pub fn is_valid(&self) -> bool {
+        let value = self
+            .value
+            .strip_prefix('"')
+            .and_then(|value| value.strip_suffix('"'))
+            .unwrap_or(&self.value);
+        let attribute_ok = |attribute: &Option<String>| {
+            attribute
+                .as_deref()
+                .is_none_or(|attribute| !attribute.chars().any(|c| c == ';' || c.is_control()))
+        };
+
+        !self.name.is_empty()
+            && self.name.bytes().all(is_token_char)
+            && value.bytes().all(is_cookie_octet)
+            && attribute_ok(&self.path)
+            && attribute_ok(&self.domain)
+    }
+}
+
+// a token character from RFC 9110, the characters a cookie name may use
+fn is_token_char(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || b"!#$%&'*+-.^_`|~".contains(&byte)
+}
+
+// a cookie-octet from RFC 6265: visible ASCII apart from '"', ',', ';' and '\'
+fn is_cookie_octet(byte: u8) -> bool {
+    matches!(byte, 0x21 | 0x23..=0x2b | 0x2d..=0x3a | 0x3c..=0x5b | 0x5d..=0x7e)
+}
+
+// implement the Display trait for the Cookie type, producing a Set-Cookie header value
+impl fmt::Display for Cookie {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.name, self.value)?;
+
+        if let Some(path) = &self.path {
+            write!(f, "; Path={}", path)?;
+        }
+        if let Some(domain) = &self.domain {
+            write!(f, "; Domain={}", domain)?;
+        }
+        if let Some(max_age) = self.max_age {
+            write!(f, "; Max-Age={}", max_age.as_secs())?;
+        }
+        if let Some(expires) = self.expires {
+            write!(f, "; Expires={}", httpdate::fmt_http_date(expires))?;
+        }
+        if self.secure {
+            f.write_str("; Secure")?;
+        }
+        if self.http_only {
+            f.write_str("; HttpOnly")?;
+        }
+        if let Some(same_site) = self.same_site {
+            write!(f, "; SameSite={}", same_site.as_str())?;
+        }
+
+        Ok(())
+    }
+}
+
+// function which parses a Cookie request header ("a=1; b=2") into name/value pairs,
+// the first occurrence of a name wins as browsers send the most specific cookie first
+pub fn parse_cookie_header(header: &str) -> HashMap<String, String> {
+    let mut cookies = HashMap::new();
+
+    for pair in header.split(';') {
+        let Some((name, value)) = pair.split_once('=') else {
+            continue;
+        };
+
+        let name = name.trim();
+        if name.is_empty() {
+            continue;
+        }
+
+        let value = value.trim();
+        let value = value
+            .strip_prefix('"')
+            .and_then(|v| v.strip_suffix('"'))
+            .unwrap_or(value);
+
+        cookies
+            .entry(name.to_string())
+            .or_insert_with(|| value.to_string());
+    }
+
+    cookies
+}
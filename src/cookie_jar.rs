@@ -0,0 +1,169 @@
+// src/cookie_jar.rs
+
+// dependencies
+use crate::cookie::Cookie;
+use crate::request::AppRequest;
+use aes_gcm::aead::rand_core::RngCore;
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use base64::Engine;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+type HmacSha256 = Hmac<Sha256>;
+
+// length of the AES-GCM nonce prepended to every encrypted value
+const NONCE_LEN: usize = 12;
+
+// struct type to represent a secret key, split into separate signing and encryption keys
+#[derive(Clone)]
+pub struct CookieKey {
+    signing: [u8; 32],
+    encryption: [u8; 32],
+}
+
+// methods for the CookieKey type
+impl CookieKey {
+    // derive a key from a master secret of at least 32 bytes, e.g. loaded from configuration,
+    // returns None when the secret is too short to be safe
+    pub fn from_secret(secret: &[u8]) -> Option<Self> {
+        if secret.len() < 32 {
+            return None;
+        }
+
+        Some(CookieKey {
+            signing: derive(secret, b"flux-web cookie signing"),
+            encryption: derive(secret, b"flux-web cookie encryption"),
+        })
+    }
+
+    // a fresh random key, cookies made with it will not survive a restart
+    pub fn generate() -> Self {
+        let mut secret = [0u8; 64];
+        OsRng.fill_bytes(&mut secret);
+        Self::from_secret(&secret).expect("64 bytes is a valid secret length")
+    }
+}
+
+// implement the Debug trait for the CookieKey type without leaking the key material
+impl std::fmt::Debug for CookieKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CookieKey").finish_non_exhaustive()
+    }
+}
+
+// struct type to represent a keyed cookie jar, which signs or encrypts cookie values with the
+// current key and accepts values made with any of the old keys so keys can be rotated
+#[derive(Debug, Clone)]
+pub struct CookieJar {
+    key: CookieKey,
+    old_keys: Vec<CookieKey>,
+}
+
+// methods for the CookieJar type
+impl CookieJar {
+    pub fn new(key: CookieKey) -> Self {
+        CookieJar {
+            key,
+            old_keys: Vec::new(),
+        }
+    }
+
+    // keys which are no longer used to create cookies but are still accepted when reading them
+    pub fn with_old_keys(mut self, old_keys: Vec<CookieKey>) -> Self {
+        self.old_keys = old_keys;
+        self
+    }
+
+    // sign the cookie value so the client can read but not modify it
+    pub fn sign(&self, mut cookie: Cookie) -> Cookie {
+        let tag = mac(&self.key, &cookie.name, &cookie.value)
+            .finalize()
+            .into_bytes();
+        let tag = URL_SAFE_NO_PAD.encode(tag);
+        cookie.value = format!("{}.{}", tag, cookie.value);
+        cookie
+    }
+
+    // encrypt the cookie value so the client can neither read nor modify it
+    pub fn encrypt(&self, mut cookie: Cookie) -> Cookie {
+        let cipher = Aes256Gcm::new(&self.key.encryption.into());
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let payload = Payload {
+            msg: cookie.value.as_bytes(),
+            aad: cookie.name.as_bytes(),
+        };
+
+        let sealed = cipher
+            .encrypt(&nonce, payload)
+            .expect("AES-GCM encryption of a cookie value cannot fail");
+
+        let mut data = nonce.to_vec();
+        data.extend_from_slice(&sealed);
+        cookie.value = URL_SAFE_NO_PAD.encode(data);
+        cookie
+    }
+
+    // the original value of a signed cookie, None if it is missing or has been tampered with
+    pub fn signed(&self, req: &AppRequest, name: &str) -> Option<String> {
+        self.verify(name, &req.cookie(name)?)
+    }
+
+    // the original value of an encrypted cookie, None if it is missing or has been tampered with
+    pub fn private(&self, req: &AppRequest, name: &str) -> Option<String> {
+        self.decrypt(name, &req.cookie(name)?)
+    }
+
+    // check a signed value against the current key and then each old key
+    pub fn verify(&self, name: &str, signed_value: &str) -> Option<String> {
+        let (tag, value) = signed_value.split_once('.')?;
+        let tag = URL_SAFE_NO_PAD.decode(tag).ok()?;
+
+        self.keys()
+            .any(|key| mac(key, name, value).verify_slice(&tag).is_ok())
+            .then(|| value.to_string())
+    }
+
+    // decrypt a value with the current key and then each old key
+    pub fn decrypt(&self, name: &str, encrypted_value: &str) -> Option<String> {
+        let data = URL_SAFE_NO_PAD.decode(encrypted_value).ok()?;
+        if data.len() <= NONCE_LEN {
+            return None;
+        }
+
+        let (nonce, sealed) = data.split_at(NONCE_LEN);
+        let nonce = Nonce::from_slice(nonce);
+
+        self.keys().find_map(|key| {
+            let cipher = Aes256Gcm::new(&key.encryption.into());
+            let payload = Payload {
+                msg: sealed,
+                aad: name.as_bytes(),
+            };
+            let plain = cipher.decrypt(nonce, payload).ok()?;
+            String::from_utf8(plain).ok()
+        })
+    }
+
+    fn keys(&self) -> impl Iterator<Item = &CookieKey> {
+        std::iter::once(&self.key).chain(self.old_keys.iter())
+    }
+}
+
+// the name is part of the signed message so a value can't be moved to another cookie
+fn mac(key: &CookieKey, name: &str, value: &str) -> HmacSha256 {
+    let mut mac =
+        <HmacSha256 as Mac>::new_from_slice(&key.signing).expect("HMAC accepts keys of any length");
+    mac.update(name.as_bytes());
+    mac.update(b"=");
+    mac.update(value.as_bytes());
+    mac
+}
+
+fn derive(secret: &[u8], purpose: &[u8]) -> [u8; 32] {
+    let mut mac =
+        <HmacSha256 as Mac>::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(purpose);
+    mac.finalize().into_bytes().into()
+}
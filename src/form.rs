@@ -0,0 +1,134 @@
+// src/form.rs
+
+// dependencies
+use crate::request::AppRequest;
+use crate::response::AppResponse;
+use std::collections::HashMap;
+
+// the only media type an HTML form without file inputs is posted as
+const FORM_URLENCODED: &str = "application/x-www-form-urlencoded";
+
+// enum type to represent the ways reading a form body can fail
+#[derive(Debug)]
+pub enum FormError {
+    // the request did not declare an application/x-www-form-urlencoded body
+    UnsupportedMediaType(Option<String>),
+    // the fields did not match the expected shape
+    Invalid(String),
+}
+
+// implement the Display trait for the FormError type
+impl std::fmt::Display for FormError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FormError::UnsupportedMediaType(Some(media_type)) => write!(
+                f,
+                "Expected a {} body but got Content-Type: {}",
+                FORM_URLENCODED, media_type
+            ),
+            FormError::UnsupportedMediaType(None) => {
+                write!(
+                    f,
+                    "Expected a {} body but got no Content-Type",
+                    FORM_URLENCODED
+                )
+            }
+            FormError::Invalid(message) => write!(f, "Invalid form body: {}", message),
+        }
+    }
+}
+
+// implement the Error trait for the FormError type
+impl std::error::Error for FormError {}
+
+// convert a FormError into the response a client should see, 415 for the wrong media type
+// and 400 for fields that don't fit
+impl From<FormError> for AppResponse {
+    fn from(err: FormError) -> Self {
+        let status = match err {
+            FormError::UnsupportedMediaType(_) => 415,
+            FormError::Invalid(_) => 400,
+        };
+
+        AppResponse::new(status, err.to_string()).with_header("Content-Type", "text/plain")
+    }
+}
+
+// form methods for the AppRequest type
+impl AppRequest {
+    // the decoded form fields, a name which appears more than once (checkboxes, multi-selects)
+    // keeps every value in the order it was sent
+    pub fn form_map(&self) -> Result<HashMap<String, Vec<String>>, FormError> {
+        self.check_form_media_type()?;
+
+        let mut fields: HashMap<String, Vec<String>> = HashMap::new();
+        for (name, value) in parse_urlencoded(&self.body) {
+            fields.entry(name).or_default().push(value);
+        }
+
+        Ok(fields)
+    }
+
+    // deserialize the form fields into a struct
+    #[cfg(feature = "serde")]
+    pub fn form<T: serde::de::DeserializeOwned>(&self) -> Result<T, FormError> {
+        self.check_form_media_type()?;
+
+        serde_urlencoded::from_bytes(&self.body).map_err(|err| FormError::Invalid(err.to_string()))
+    }
+
+    fn check_form_media_type(&self) -> Result<(), FormError> {
+        match self.media_type() {
+            Some(media_type) if media_type == FORM_URLENCODED => Ok(()),
+            other => Err(FormError::UnsupportedMediaType(other)),
+        }
+    }
+}
+
+// function which splits an application/x-www-form-urlencoded string into decoded name/value pairs
+pub fn parse_urlencoded(input: &[u8]) -> Vec<(String, String)> {
+    input
+        .split(|&byte| byte == b'&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let mut parts = pair.splitn(2, |&byte| byte == b'=');
+            let name = decode_component(parts.next().unwrap_or_default());
+            let value = decode_component(parts.next().unwrap_or_default());
+            (name, value)
+        })
+        .collect()
+}
+
+// '+' is a space, %XX is a byte, and a '%' that isn't followed by two hex digits is kept as is
+fn decode_component(input: &[u8]) -> String {
+    let mut decoded = Vec::with_capacity(input.len());
+    let mut i = 0;
+
+    while i < input.len() {
+        match input[i] {
+            b'+' => decoded.push(b' '),
+            b'%' if i + 2 < input.len() => {
+                match (hex_value(input[i + 1]), hex_value(input[i + 2])) {
+                    (Some(high), Some(low)) => {
+                        decoded.push(high << 4 | low);
+                        i += 2;
+                    }
+                    _ => decoded.push(b'%'),
+                }
+            }
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn hex_value(byte: u8) -> Option<u8> {
+    match byte {
+        b'0'..=b'9' => Some(byte - b'0'),
+        b'a'..=b'f' => Some(byte - b'a' + 10),
+        b'A'..=b'F' => Some(byte - b'A' + 10),
+        _ => None,
+    }
+}
@@ -3,10 +3,23 @@
 // dependencies
 use crate::request::AppRequest;
 use crate::response::AppResponse;
+#[cfg(feature = "websocket")]
+use crate::websocket::WebSocket;
+use std::future::Future;
+use std::ops::ControlFlow;
+use std::pin::Pin;
+
+// a boxed future which can be stored and sent across async tasks
+pub type BoxFuture<T> = Pin<Box<dyn Future<Output = T> + Send>>;
 
 // a trait which enables creation of handlers
 pub trait Handler: Send + Sync {
     fn handle(&self, req: &AppRequest) -> AppResponse;
+
+    // what the router calls, handler chains override it so their steps can change the request
+    fn handle_mut(&self, req: &mut AppRequest) -> AppResponse {
+        self.handle(req)
+    }
 }
 
 // Automatically implement Handler for any closure that:
@@ -22,4 +35,115 @@ where
     fn handle(&self, req: &AppRequest) -> AppResponse {
         self(req)
     }
-}
\ No newline at end of file
+}
+
+// a trait which enables creation of the steps before the handler in a chain like
+// app.get("/x", (check_auth, load_user, handler)), a step passes the request along with
+// ControlFlow::Continue(()) or answers it itself with ControlFlow::Break(response)
+pub trait Step: Send + Sync {
+    fn run(&self, req: &mut AppRequest) -> ControlFlow<AppResponse>;
+}
+
+// Automatically implement Step for any closure that takes a mutable reference to AppRequest,
+// so a step can leave values in req.extensions for the ones after it
+impl<F> Step for F
+where
+    F: for<'a> Fn(&'a mut AppRequest) -> ControlFlow<AppResponse> + Send + Sync,
+{
+    fn run(&self, req: &mut AppRequest) -> ControlFlow<AppResponse> {
+        self(req)
+    }
+}
+
+// a trait which enables creation of the callbacks added with App::param, which get the request
+// and the value of a path parameter and, like a Step, pass the request along or answer it
+pub trait ParamHandler: Send + Sync {
+    fn run(&self, req: &mut AppRequest, value: &str) -> ControlFlow<AppResponse>;
+}
+
+// Automatically implement ParamHandler for any closure that takes a mutable reference to
+// AppRequest and the parameter's value, e.g. |req: &mut AppRequest, id: &str| ...
+impl<F> ParamHandler for F
+where
+    F: for<'a, 'b> Fn(&'a mut AppRequest, &'b str) -> ControlFlow<AppResponse> + Send + Sync,
+{
+    fn run(&self, req: &mut AppRequest, value: &str) -> ControlFlow<AppResponse> {
+        self(req, value)
+    }
+}
+
+// implement the Debug trait for param callbacks, so the App holding them can still be printed
+impl std::fmt::Debug for dyn ParamHandler {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<param handler>")
+    }
+}
+
+// implement Handler for tuples of up to seven steps followed by a handler, the steps run in
+// order and the first one to break answers the request
+macro_rules! handler_chain {
+    ($($step:ident),+) => {
+        impl<$($step: Step,)+ H: Handler> Handler for ($($step,)+ H) {
+            // called outside the router the steps work on a copy of the request
+            fn handle(&self, req: &AppRequest) -> AppResponse {
+                self.handle_mut(&mut req.copy())
+            }
+
+            #[allow(non_snake_case)]
+            fn handle_mut(&self, req: &mut AppRequest) -> AppResponse {
+                let ($($step,)+ handler) = self;
+                $(
+                    if let ControlFlow::Break(response) = $step.run(req) {
+                        return response;
+                    }
+                )+
+                handler.handle_mut(req)
+            }
+        }
+    };
+}
+
+handler_chain!(S1);
+handler_chain!(S1, S2);
+handler_chain!(S1, S2, S3);
+handler_chain!(S1, S2, S3, S4);
+handler_chain!(S1, S2, S3, S4, S5);
+handler_chain!(S1, S2, S3, S4, S5, S6);
+handler_chain!(S1, S2, S3, S4, S5, S6, S7);
+
+// a trait which enables creation of async handlers that own the request, so they can
+// read the body as a stream with AppRequest::body_stream()
+pub trait StreamHandler: Send + Sync {
+    fn handle(&self, req: AppRequest) -> BoxFuture<AppResponse>;
+}
+
+// Automatically implement StreamHandler for any closure that takes an AppRequest by value
+// and returns a future resolving to an AppResponse, e.g. |req| async move { ... }
+impl<F, Fut> StreamHandler for F
+where
+    F: Fn(AppRequest) -> Fut + Send + Sync,
+    Fut: Future<Output = AppResponse> + Send + 'static,
+{
+    fn handle(&self, req: AppRequest) -> BoxFuture<AppResponse> {
+        Box::pin(self(req))
+    }
+}
+
+// a trait which enables creation of WebSocket handlers, which own the upgraded connection
+#[cfg(feature = "websocket")]
+pub trait WsHandler: Send + Sync {
+    fn handle(&self, socket: WebSocket) -> BoxFuture<()>;
+}
+
+// Automatically implement WsHandler for any closure that takes a WebSocket by value and
+// returns a future, e.g. |mut socket| async move { ... }
+#[cfg(feature = "websocket")]
+impl<F, Fut> WsHandler for F
+where
+    F: Fn(WebSocket) -> Fut + Send + Sync,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    fn handle(&self, socket: WebSocket) -> BoxFuture<()> {
+        Box::pin(self(socket))
+    }
+}
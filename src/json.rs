@@ -0,0 +1,78 @@
+// src/json.rs
+
+// dependencies
+use crate::request::AppRequest;
+use crate::response::AppResponse;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+// enum type to represent the ways reading a JSON request body can fail
+#[derive(Debug)]
+pub enum JsonError {
+    // the request declared a Content-Type other than JSON
+    UnsupportedMediaType(String),
+    // the body was not valid JSON, or did not match the expected shape
+    Invalid(serde_json::Error),
+}
+
+// implement the Display trait for the JsonError type
+impl std::fmt::Display for JsonError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JsonError::UnsupportedMediaType(content_type) => {
+                write!(
+                    f,
+                    "Expected a JSON body but got Content-Type: {}",
+                    content_type
+                )
+            }
+            JsonError::Invalid(err) => write!(f, "Invalid JSON body: {}", err),
+        }
+    }
+}
+
+// implement the Error trait for the JsonError type
+impl std::error::Error for JsonError {}
+
+// convert a JsonError into the response a client should see, 415 for the wrong media type
+// and 400 for a malformed body, so handlers can simply return err.into()
+impl From<JsonError> for AppResponse {
+    fn from(err: JsonError) -> Self {
+        let status = match err {
+            JsonError::UnsupportedMediaType(_) => 415,
+            JsonError::Invalid(_) => 400,
+        };
+
+        AppResponse::new(status, err.to_string()).with_header("Content-Type", "text/plain")
+    }
+}
+
+// JSON methods for the AppRequest type
+impl AppRequest {
+    // deserialize the request body as JSON, a missing Content-Type is accepted
+    pub fn json<T: DeserializeOwned>(&self) -> Result<T, JsonError> {
+        if let Some(media_type) = self.media_type()
+            && media_type != "application/json"
+            && !media_type.ends_with("+json")
+        {
+            return Err(JsonError::UnsupportedMediaType(media_type));
+        }
+
+        serde_json::from_slice(&self.body).map_err(JsonError::Invalid)
+    }
+}
+
+// JSON methods for the AppResponse type
+impl AppResponse {
+    // a 200 response with the value serialized as the body and a JSON Content-Type,
+    // a value which can't be serialized produces a 500 instead
+    pub fn json<T: Serialize + ?Sized>(value: &T) -> Self {
+        match serde_json::to_vec(value) {
+            Ok(bytes) => {
+                AppResponse::with_bytes(200, bytes).with_header("Content-Type", "application/json")
+            }
+            Err(err) => AppResponse::internal_error(format!("Failed to serialize JSON: {}", err))
+                .with_header("Content-Type", "text/plain"),
+        }
+    }
+}
@@ -1,17 +1,68 @@
 // src/lib/lib.rs
 
 // module declarations
-mod method;
 mod handler;
+mod log;
+mod method;
+mod range;
+mod static_files;
+#[cfg(feature = "tera")]
+mod templates;
+mod version;
 
 // public module declarations
+pub mod access_log;
 pub mod app;
+pub mod body;
+#[cfg(feature = "compression")]
+pub mod compression;
+pub mod conditional;
+pub mod cookie;
+#[cfg(feature = "secure-cookies")]
+pub mod cookie_jar;
+pub mod form;
+pub mod middleware;
+#[cfg(feature = "serde")]
+pub mod json;
+pub mod multipart;
+pub mod negotiation;
 pub mod request;
 pub mod response;
 pub mod router;
+pub mod sse;
+pub mod template;
+#[cfg(feature = "websocket")]
+pub mod websocket;
 
 // re-exports
+pub use access_log::*;
 pub use app::*;
+pub use body::*;
+#[cfg(feature = "compression")]
+pub use compression::*;
+pub use conditional::*;
+pub use cookie::*;
+#[cfg(feature = "secure-cookies")]
+pub use cookie_jar::*;
+pub use form::*;
+pub use middleware::*;
+#[cfg(feature = "serde")]
+pub use json::*;
+pub use multipart::*;
+pub use negotiation::*;
 pub use request::*;
 pub use response::*;
 pub use router::*;
+pub use sse::*;
+pub use template::*;
+#[cfg(feature = "websocket")]
+pub use websocket::*;
+pub use method::Method;
+pub use version::Version;
+
+// the typed status code used by AppResponse
+pub use hyper::StatusCode;
+
+// the template engine behind AppRequest::render, for building a tera::Context
+#[cfg(feature = "tera")]
+pub use tera;
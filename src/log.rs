@@ -0,0 +1,24 @@
+// src/log.rs
+
+// report a framework error, through tracing with the tracing feature and on stderr without it
+macro_rules! log_error {
+    ($($arg:tt)+) => {{
+        #[cfg(feature = "tracing")]
+        tracing::error!($($arg)+);
+        #[cfg(not(feature = "tracing"))]
+        eprintln!("Error: {}", format_args!($($arg)+));
+    }};
+}
+
+// report something the server did, through tracing with the tracing feature and on stdout
+// without it
+macro_rules! log_info {
+    ($($arg:tt)+) => {{
+        #[cfg(feature = "tracing")]
+        tracing::info!($($arg)+);
+        #[cfg(not(feature = "tracing"))]
+        println!($($arg)+);
+    }};
+}
+
+pub(crate) use {log_error, log_info};
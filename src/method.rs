@@ -1,7 +1,7 @@
 // src/lib/method.rs
 
 // enum type to represent an HTTP method
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub enum Method {
     Get,
     Post,
@@ -10,6 +10,19 @@ pub enum Method {
     Patch,
 }
 
+// methods for the Method type
+impl Method {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Method::Get => "GET",
+            Method::Post => "POST",
+            Method::Put => "PUT",
+            Method::Delete => "DELETE",
+            Method::Patch => "PATCH",
+        }
+    }
+}
+
 // function which takes a hyper::Method as input and converts it to a flux-web Method
 pub fn convert_method(m: &hyper::Method) -> Method {
     match *m {
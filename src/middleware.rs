@@ -0,0 +1,163 @@
+// src/middleware.rs
+
+// dependencies
+use crate::app::{App, dispatch};
+use crate::handler::BoxFuture;
+use crate::method::Method;
+use crate::request::AppRequest;
+use crate::response::AppResponse;
+use std::future::Future;
+use std::sync::Arc;
+
+// a trait which enables creation of middleware, which wraps everything after it in the chain
+// and can change the request, answer it itself, or change the response next produces
+pub trait Middleware: Send + Sync {
+    fn handle(&self, req: AppRequest, next: Next) -> BoxFuture<AppResponse>;
+}
+
+// Automatically implement Middleware for any closure that takes the request and the rest of
+// the chain by value and returns a future resolving to an AppResponse, e.g.
+// |req, next: Next| async move { next.run(req).await }
+impl<F, Fut> Middleware for F
+where
+    F: Fn(AppRequest, Next) -> Fut + Send + Sync,
+    Fut: Future<Output = AppResponse> + Send + 'static,
+{
+    fn handle(&self, req: AppRequest, next: Next) -> BoxFuture<AppResponse> {
+        Box::pin(self(req, next))
+    }
+}
+
+// implement the Debug trait for middleware, so the App holding it can still be printed
+impl std::fmt::Debug for dyn Middleware {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "<middleware>")
+    }
+}
+
+// struct type to represent middleware added to an App and the path prefix it applies to,
+// "/" for every request
+#[derive(Debug, Clone)]
+pub(crate) struct Layer {
+    prefix: String,
+    middleware: Arc<dyn Middleware>,
+}
+
+// methods for the Layer type
+impl Layer {
+    pub(crate) fn new(prefix: &str, middleware: Arc<dyn Middleware>) -> Self {
+        Layer {
+            // "/admin/" and "admin" both mean "/admin"
+            prefix: format!("/{}", prefix.trim_matches('/')),
+            middleware,
+        }
+    }
+
+    // "/admin" applies to /admin and /admin/users, but not to /administrator
+    fn applies_to(&self, path: &str) -> bool {
+        self.prefix == "/"
+            || path
+                .strip_prefix(&self.prefix)
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with('/'))
+    }
+}
+
+// enum type to represent how far along the chain a request is, the App's middleware come
+// first, then the middleware of the matched route
+#[derive(Debug, Clone)]
+enum Stage {
+    App(usize),
+    Route {
+        route: (Method, String),
+        index: usize,
+    },
+}
+
+// struct type to represent the rest of a middleware chain, the remaining middleware followed
+// by the route's handler (or static files, or the 404)
+pub struct Next {
+    app: Arc<App>,
+    stage: Stage,
+}
+
+// methods for the Next type
+impl Next {
+    pub(crate) fn new(app: Arc<App>) -> Self {
+        Next {
+            app,
+            stage: Stage::App(0),
+        }
+    }
+
+    // pass the request on and wait for the response, a middleware which never calls this
+    // answers the request itself
+    pub async fn run(self, req: AppRequest) -> AppResponse {
+        self.call(req).await
+    }
+
+    // boxed, as the chain calls itself once per middleware
+    fn call(self, req: AppRequest) -> BoxFuture<AppResponse> {
+        Box::pin(async move {
+            let app = self.app;
+            match self.stage {
+                // prefixes are checked against the path as the middleware before left it
+                Stage::App(index) => {
+                    let layer = app
+                        .middleware
+                        .iter()
+                        .enumerate()
+                        .skip(index)
+                        .find(|(_, layer)| layer.applies_to(&req.path))
+                        .map(|(index, layer)| (index, layer.middleware.clone()));
+
+                    if let Some((index, middleware)) = layer {
+                        let next = Next {
+                            app,
+                            stage: Stage::App(index + 1),
+                        };
+                        return middleware.handle(req, next).await;
+                    }
+
+                    match app.route_middleware_key(&req) {
+                        Some(route) => {
+                            let next = Next {
+                                app,
+                                stage: Stage::Route { route, index: 0 },
+                            };
+                            next.call(req).await
+                        }
+                        None => dispatch(app, req).await,
+                    }
+                }
+                Stage::Route { route, index } => {
+                    let middleware = app
+                        .route_middleware
+                        .get(&route)
+                        .and_then(|chain| chain.get(index))
+                        .cloned();
+
+                    match middleware {
+                        Some(middleware) => {
+                            let next = Next {
+                                app,
+                                stage: Stage::Route {
+                                    route,
+                                    index: index + 1,
+                                },
+                            };
+                            middleware.handle(req, next).await
+                        }
+                        None => dispatch(app, req).await,
+                    }
+                }
+            }
+        })
+    }
+}
+
+// implement the Debug trait for the Next type
+impl std::fmt::Debug for Next {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Next").field("stage", &self.stage).finish()
+    }
+}
@@ -0,0 +1,534 @@
+// src/multipart.rs
+
+// dependencies
+use crate::body::RequestBody;
+use crate::request::AppRequest;
+use crate::response::AppResponse;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::fs::{File, OpenOptions};
+use tokio::io::AsyncWriteExt;
+
+// part headers larger than this are rejected rather than buffered
+const MAX_HEADER_SIZE: usize = 16 * 1024;
+
+// used to give every spooled upload a unique file name
+static UPLOAD_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+// struct type to represent the size limits applied while reading a multipart body
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MultipartLimits {
+    pub max_part_size: u64,
+    pub max_total_size: u64,
+}
+
+// methods for the MultipartLimits type
+impl MultipartLimits {
+    // the largest single field or file, in bytes
+    pub fn max_part_size(mut self, bytes: u64) -> Self {
+        self.max_part_size = bytes;
+        self
+    }
+
+    // the largest whole request body, in bytes
+    pub fn max_total_size(mut self, bytes: u64) -> Self {
+        self.max_total_size = bytes;
+        self
+    }
+}
+
+// implement the Default trait for the MultipartLimits type, 10 MiB per part and 50 MiB in total
+impl Default for MultipartLimits {
+    fn default() -> Self {
+        MultipartLimits {
+            max_part_size: 10 * 1024 * 1024,
+            max_total_size: 50 * 1024 * 1024,
+        }
+    }
+}
+
+// enum type to represent the ways reading a multipart body can fail
+#[derive(Debug)]
+pub enum MultipartError {
+    // the request did not declare a multipart/form-data body
+    UnsupportedMediaType(Option<String>),
+    // the Content-Type had no boundary parameter
+    MissingBoundary,
+    // the body does not follow the multipart format
+    Malformed(&'static str),
+    // a single field or file was larger than max_part_size
+    PartTooLarge { name: String, limit: u64 },
+    // the whole body was larger than max_total_size
+    TotalTooLarge { limit: u64 },
+    // reading from the connection or writing a temp file failed
+    Io(io::Error),
+}
+
+// implement the Display trait for the MultipartError type
+impl std::fmt::Display for MultipartError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MultipartError::UnsupportedMediaType(Some(media_type)) => write!(
+                f,
+                "Expected a multipart/form-data body but got Content-Type: {}",
+                media_type
+            ),
+            MultipartError::UnsupportedMediaType(None) => {
+                write!(
+                    f,
+                    "Expected a multipart/form-data body but got no Content-Type"
+                )
+            }
+            MultipartError::MissingBoundary => {
+                write!(f, "Multipart Content-Type is missing its boundary")
+            }
+            MultipartError::Malformed(reason) => write!(f, "Malformed multipart body: {}", reason),
+            MultipartError::PartTooLarge { name, limit } => {
+                write!(f, "Field '{}' is larger than {} bytes", name, limit)
+            }
+            MultipartError::TotalTooLarge { limit } => {
+                write!(f, "Multipart body is larger than {} bytes", limit)
+            }
+            MultipartError::Io(err) => write!(f, "Failed to read multipart body: {}", err),
+        }
+    }
+}
+
+// implement the Error trait for the MultipartError type
+impl std::error::Error for MultipartError {}
+
+// implement the From trait to convert io errors into a MultipartError
+impl From<io::Error> for MultipartError {
+    fn from(err: io::Error) -> Self {
+        MultipartError::Io(err)
+    }
+}
+
+// convert a MultipartError into the response a client should see
+impl From<MultipartError> for AppResponse {
+    fn from(err: MultipartError) -> Self {
+        let status = match err {
+            MultipartError::UnsupportedMediaType(_) => 415,
+            MultipartError::MissingBoundary | MultipartError::Malformed(_) => 400,
+            MultipartError::PartTooLarge { .. } | MultipartError::TotalTooLarge { .. } => 413,
+            MultipartError::Io(_) => 500,
+        };
+
+        AppResponse::new(status, err.to_string()).with_header("Content-Type", "text/plain")
+    }
+}
+
+// struct type to represent a single part of a multipart body
+#[derive(Debug)]
+pub struct Part {
+    pub name: String,
+    pub content_type: Option<String>,
+    pub data: PartData,
+}
+
+// enum type to represent the contents of a part, text fields are kept in memory while
+// anything sent with a filename is spooled to a temporary file
+#[derive(Debug)]
+pub enum PartData {
+    Text(String),
+    File(UploadedFile),
+}
+
+// struct type to represent an uploaded file spooled to disk, the temporary file is
+// deleted when this is dropped unless it has been persisted somewhere else first
+#[derive(Debug)]
+pub struct UploadedFile {
+    pub filename: String,
+    pub size: u64,
+    path: PathBuf,
+    keep: bool,
+}
+
+// methods for the UploadedFile type
+impl UploadedFile {
+    // where the upload currently lives on disk
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    // move the upload to its final location, the filename sent by the client is not
+    // used for this as it can't be trusted
+    pub async fn persist(mut self, destination: impl AsRef<Path>) -> io::Result<()> {
+        let destination = destination.as_ref();
+
+        // rename is not possible across file systems, fall back to copying
+        if tokio::fs::rename(&self.path, destination).await.is_err() {
+            tokio::fs::copy(&self.path, destination).await?;
+            tokio::fs::remove_file(&self.path).await?;
+        }
+
+        self.keep = true;
+        Ok(())
+    }
+}
+
+// implement the Drop trait for the UploadedFile type so temporary files don't pile up
+impl Drop for UploadedFile {
+    fn drop(&mut self) {
+        if !self.keep {
+            let _ = std::fs::remove_file(&self.path);
+        }
+    }
+}
+
+// where the parser is in the body
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Preamble,
+    Headers,
+    Done,
+}
+
+// struct type to represent a multipart/form-data body being read part by part
+#[derive(Debug)]
+pub struct Multipart {
+    body: RequestBody,
+    delimiter: Vec<u8>,
+    buffer: Vec<u8>,
+    state: State,
+    eof: bool,
+    total: u64,
+    limits: MultipartLimits,
+    temp_dir: PathBuf,
+}
+
+// methods for the Multipart type
+impl Multipart {
+    // start reading the body of a multipart/form-data request
+    pub fn from_request(
+        req: &mut AppRequest,
+        limits: MultipartLimits,
+    ) -> Result<Self, MultipartError> {
+        let media_type = req.media_type();
+        if media_type.as_deref() != Some("multipart/form-data") {
+            return Err(MultipartError::UnsupportedMediaType(media_type));
+        }
+
+        let boundary = req
+            .headers
+            .get("content-type")
+            .and_then(|value| header_param(value, "boundary"))
+            .filter(|boundary| !boundary.is_empty() && boundary.len() <= 70)
+            .ok_or(MultipartError::MissingBoundary)?;
+
+        // refuse early when the client tells us the body is too big
+        if let Some(length) = req
+            .headers
+            .get("content-length")
+            .and_then(|value| value.parse::<u64>().ok())
+            && length > limits.max_total_size
+        {
+            return Err(MultipartError::TotalTooLarge {
+                limit: limits.max_total_size,
+            });
+        }
+
+        Ok(Multipart {
+            body: req.body_stream(),
+            delimiter: format!("\r\n--{}", boundary).into_bytes(),
+            // the first boundary is not preceded by a line break, pretend it is so every
+            // delimiter in the body looks the same
+            buffer: b"\r\n".to_vec(),
+            state: State::Preamble,
+            eof: false,
+            total: 0,
+            limits,
+            temp_dir: std::env::temp_dir(),
+        })
+    }
+
+    // spool uploaded files into this directory instead of the system temp directory
+    pub fn temp_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.temp_dir = dir.into();
+        self
+    }
+
+    // read the next part, None once the closing boundary has been reached
+    pub async fn next_part(&mut self) -> Result<Option<Part>, MultipartError> {
+        if self.state == State::Preamble {
+            self.skip_to_delimiter().await?;
+            self.after_delimiter().await?;
+        }
+
+        if self.state == State::Done {
+            return Ok(None);
+        }
+
+        let headers = self.read_headers().await?;
+        let disposition = headers
+            .iter()
+            .find(|(name, _)| name == "content-disposition")
+            .map(|(_, value)| value.as_str())
+            .ok_or(MultipartError::Malformed("part has no Content-Disposition"))?;
+
+        let name = header_param(disposition, "name")
+            .ok_or(MultipartError::Malformed("part has no name"))?;
+        let filename = header_param(disposition, "filename");
+        let content_type = headers
+            .iter()
+            .find(|(name, _)| name == "content-type")
+            .map(|(_, value)| value.clone());
+
+        let data = match filename {
+            Some(filename) => PartData::File(self.spool_file(&name, filename).await?),
+            None => PartData::Text(self.read_text(&name).await?),
+        };
+
+        self.after_delimiter().await?;
+
+        Ok(Some(Part {
+            name,
+            content_type,
+            data,
+        }))
+    }
+
+    // pull another chunk from the body into the buffer, false once the body is finished
+    async fn fill(&mut self) -> Result<bool, MultipartError> {
+        if self.eof {
+            return Ok(false);
+        }
+
+        match self.body.chunk().await? {
+            Some(chunk) => {
+                self.total += chunk.len() as u64;
+                if self.total > self.limits.max_total_size {
+                    return Err(MultipartError::TotalTooLarge {
+                        limit: self.limits.max_total_size,
+                    });
+                }
+                self.buffer.extend_from_slice(&chunk);
+                Ok(true)
+            }
+            None => {
+                self.eof = true;
+                Ok(false)
+            }
+        }
+    }
+
+    // discard everything up to and including the first delimiter
+    async fn skip_to_delimiter(&mut self) -> Result<(), MultipartError> {
+        loop {
+            if let Some(index) = find(&self.buffer, &self.delimiter) {
+                self.buffer.drain(..index + self.delimiter.len());
+                return Ok(());
+            }
+
+            // keep just enough to catch a delimiter split across chunks
+            let keep = self.delimiter.len() - 1;
+            if self.buffer.len() > keep {
+                self.buffer.drain(..self.buffer.len() - keep);
+            }
+
+            if !self.fill().await? {
+                return Err(MultipartError::Malformed("no boundary found in body"));
+            }
+        }
+    }
+
+    // after a delimiter comes either "--" for the end of the body or a line break
+    async fn after_delimiter(&mut self) -> Result<(), MultipartError> {
+        loop {
+            if self.buffer.starts_with(b"--") {
+                self.state = State::Done;
+                return Ok(());
+            }
+
+            // senders may pad the delimiter line with whitespace
+            let padding = self
+                .buffer
+                .iter()
+                .take_while(|&&byte| byte == b' ' || byte == b'\t')
+                .count();
+            if self.buffer[padding..].starts_with(b"\r\n") {
+                self.buffer.drain(..padding + 2);
+                self.state = State::Headers;
+                return Ok(());
+            }
+
+            if self.buffer.len() > padding + 1 || !self.fill().await? {
+                return Err(MultipartError::Malformed("invalid boundary line"));
+            }
+        }
+    }
+
+    // read the header block of a part, header names are lowercased
+    async fn read_headers(&mut self) -> Result<Vec<(String, String)>, MultipartError> {
+        let end = loop {
+            if self.buffer.starts_with(b"\r\n") {
+                break 0;
+            }
+            if let Some(index) = find(&self.buffer, b"\r\n\r\n") {
+                break index + 2;
+            }
+            if self.buffer.len() > MAX_HEADER_SIZE {
+                return Err(MultipartError::Malformed("part headers are too large"));
+            }
+            if !self.fill().await? {
+                return Err(MultipartError::Malformed("body ended inside part headers"));
+            }
+        };
+
+        let block: Vec<u8> = self.buffer.drain(..end + 2).collect();
+        let block = String::from_utf8_lossy(&block[..end]);
+
+        Ok(block
+            .split("\r\n")
+            .filter_map(|line| line.split_once(':'))
+            .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+            .collect())
+    }
+
+    // the next piece of the current part's body, None once its delimiter has been consumed
+    async fn next_chunk(&mut self) -> Result<Option<Vec<u8>>, MultipartError> {
+        loop {
+            if let Some(index) = find(&self.buffer, &self.delimiter) {
+                if index == 0 {
+                    self.buffer.drain(..self.delimiter.len());
+                    return Ok(None);
+                }
+                return Ok(Some(self.buffer.drain(..index).collect()));
+            }
+
+            // everything except a possible partial delimiter at the end is part data
+            let safe = self.buffer.len().saturating_sub(self.delimiter.len() - 1);
+            if safe > 0 {
+                return Ok(Some(self.buffer.drain(..safe).collect()));
+            }
+
+            if !self.fill().await? {
+                return Err(MultipartError::Malformed("body ended inside a part"));
+            }
+        }
+    }
+
+    async fn read_text(&mut self, name: &str) -> Result<String, MultipartError> {
+        let mut text = Vec::new();
+
+        while let Some(chunk) = self.next_chunk().await? {
+            text.extend_from_slice(&chunk);
+            self.check_part_size(name, text.len() as u64)?;
+        }
+
+        String::from_utf8(text).map_err(|_| MultipartError::Malformed("text field is not UTF-8"))
+    }
+
+    async fn spool_file(
+        &mut self,
+        name: &str,
+        filename: String,
+    ) -> Result<UploadedFile, MultipartError> {
+        let (mut file, mut upload) = self.create_temp_file(filename).await?;
+
+        while let Some(chunk) = self.next_chunk().await? {
+            upload.size += chunk.len() as u64;
+            self.check_part_size(name, upload.size)?;
+            file.write_all(&chunk).await?;
+        }
+
+        file.flush().await?;
+        Ok(upload)
+    }
+
+    async fn create_temp_file(
+        &self,
+        filename: String,
+    ) -> Result<(File, UploadedFile), MultipartError> {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_nanos())
+            .unwrap_or_default();
+        let counter = UPLOAD_COUNTER.fetch_add(1, Ordering::Relaxed);
+        let path = self.temp_dir.join(format!(
+            "flux-web-upload-{}-{}-{}",
+            std::process::id(),
+            nanos,
+            counter
+        ));
+
+        let file = OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .await?;
+
+        Ok((
+            file,
+            UploadedFile {
+                filename,
+                size: 0,
+                path,
+                keep: false,
+            },
+        ))
+    }
+
+    fn check_part_size(&self, name: &str, size: u64) -> Result<(), MultipartError> {
+        if size > self.limits.max_part_size {
+            return Err(MultipartError::PartTooLarge {
+                name: name.to_string(),
+                limit: self.limits.max_part_size,
+            });
+        }
+        Ok(())
+    }
+}
+
+// a parameter from a header value such as `form-data; name="file"; filename="a;b.txt"`,
+// quoted values may contain separators and backslash escapes
+fn header_param(value: &str, param: &str) -> Option<String> {
+    let mut rest = value.split_once(';')?.1;
+
+    loop {
+        let (key, after_key) = rest.split_once('=')?;
+        let key = key.trim();
+        let after_key = after_key.trim_start();
+
+        let (parsed, remainder) = match after_key.strip_prefix('"') {
+            Some(quoted) => {
+                let mut parsed = String::new();
+                let mut chars = quoted.char_indices();
+                let mut end = quoted.len();
+                while let Some((index, c)) = chars.next() {
+                    match c {
+                        '\\' => parsed.extend(chars.next().map(|(_, escaped)| escaped)),
+                        '"' => {
+                            end = index + 1;
+                            break;
+                        }
+                        c => parsed.push(c),
+                    }
+                }
+                let remainder = &quoted[end..];
+                (parsed, remainder.split_once(';').map_or("", |(_, r)| r))
+            }
+            None => match after_key.split_once(';') {
+                Some((unquoted, remainder)) => (unquoted.trim().to_string(), remainder),
+                None => (after_key.trim().to_string(), ""),
+            },
+        };
+
+        if key.eq_ignore_ascii_case(param) {
+            return Some(parsed);
+        }
+        if remainder.is_empty() {
+            return None;
+        }
+        rest = remainder;
+    }
+}
+
+// position of the first occurrence of needle in haystack
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
@@ -0,0 +1,212 @@
+// src/negotiation.rs
+
+// dependencies
+use crate::request::AppRequest;
+use crate::response::AppResponse;
+
+// struct type to represent a single media range from an Accept header, e.g. text/*;q=0.8
+#[derive(Debug, Clone, PartialEq)]
+struct MediaRange {
+    kind: String,
+    subtype: String,
+    q: f32,
+    position: usize,
+}
+
+// methods for the MediaRange type
+impl MediaRange {
+    // how closely this range matches a media type, None if it doesn't match at all,
+    // an exact type beats type/* which beats */*
+    fn specificity(&self, kind: &str, subtype: &str) -> Option<u8> {
+        if self.kind == "*" {
+            Some(0)
+        } else if self.kind != kind {
+            None
+        } else if self.subtype == "*" {
+            Some(1)
+        } else if self.subtype == subtype {
+            Some(2)
+        } else {
+            None
+        }
+    }
+}
+
+// content negotiation methods for the AppRequest type
+impl AppRequest {
+    // the offered media type the client prefers, None when it accepts none of them,
+    // a request without an Accept header accepts anything and gets the first offer
+    pub fn accepts<'a>(&self, offers: &[&'a str]) -> Option<&'a str> {
+        let ranges = match self.headers.get("accept") {
+            Some(header) if !header.trim().is_empty() => parse_accept(header),
+            _ => return offers.first().copied(),
+        };
+
+        let mut best: Option<(&'a str, f32, u8, usize)> = None;
+
+        for &offer in offers {
+            let (kind, subtype) = split_media_type(offer);
+
+            // the most specific matching range decides the quality of an offer
+            let Some((range, specificity)) = ranges
+                .iter()
+                .filter_map(|range| Some((range, range.specificity(&kind, &subtype)?)))
+                .max_by_key(|(_, specificity)| *specificity)
+            else {
+                continue;
+            };
+
+            if range.q <= 0.0 {
+                continue;
+            }
+
+            // higher quality wins, then the more specific match, then the range the client
+            // listed first, and finally the order the offers were given in
+            let better = match best {
+                None => true,
+                Some((_, q, best_specificity, position)) => {
+                    range.q > q
+                        || (range.q == q && specificity > best_specificity)
+                        || (range.q == q
+                            && specificity == best_specificity
+                            && range.position < position)
+                }
+            };
+
+            if better {
+                best = Some((offer, range.q, specificity, range.position));
+            }
+        }
+
+        best.map(|(offer, ..)| offer)
+    }
+}
+
+// a response builder which is only run when its media type is chosen
+type Builder<'a> = Box<dyn FnOnce() -> AppResponse + 'a>;
+
+// struct type to represent a response chosen by the request's Accept header, see AppResponse::format
+pub struct Format<'a> {
+    req: &'a AppRequest,
+    builders: Vec<(&'a str, Builder<'a>)>,
+    fallback: Option<Builder<'a>>,
+}
+
+// methods for the Format type
+impl<'a> Format<'a> {
+    // build the response with this closure when the client prefers media_type
+    pub fn on(mut self, media_type: &'a str, builder: impl FnOnce() -> AppResponse + 'a) -> Self {
+        self.builders.push((media_type, Box::new(builder)));
+        self
+    }
+
+    // build the response with this closure when the client accepts none of the media types,
+    // without it such requests get a 406 Not Acceptable
+    pub fn fallback(mut self, builder: impl FnOnce() -> AppResponse + 'a) -> Self {
+        self.fallback = Some(Box::new(builder));
+        self
+    }
+
+    // negotiate and run the chosen builder, the response gets the chosen Content-Type unless
+    // the builder set one, and a Vary header so caches keep the variants apart
+    pub fn respond(mut self) -> AppResponse {
+        let offers: Vec<&str> = self
+            .builders
+            .iter()
+            .map(|(media_type, _)| *media_type)
+            .collect();
+
+        let response = match self.req.accepts(&offers) {
+            Some(chosen) => {
+                let index = offers.iter().position(|offer| *offer == chosen).unwrap();
+                let (media_type, builder) = self.builders.swap_remove(index);
+                let response = builder();
+
+                if response
+                    .headers
+                    .keys()
+                    .any(|key| key.eq_ignore_ascii_case("content-type"))
+                {
+                    response
+                } else {
+                    response.with_header("Content-Type", media_type)
+                }
+            }
+            None => match self.fallback {
+                Some(fallback) => fallback(),
+                None => AppResponse::new(
+                    406,
+                    format!("Not Acceptable, available types: {}", offers.join(", ")),
+                )
+                .with_header("Content-Type", "text/plain"),
+            },
+        };
+
+        response.add_vary("Accept")
+    }
+}
+
+// implement the From trait so a Format can be returned straight from a handler with .into()
+impl From<Format<'_>> for AppResponse {
+    fn from(format: Format<'_>) -> Self {
+        format.respond()
+    }
+}
+
+// content negotiation methods for the AppResponse type
+impl AppResponse {
+    // pick a response by the request's Accept header, like res.format() in Express
+    pub fn format(req: &AppRequest) -> Format<'_> {
+        Format {
+            req,
+            builders: Vec::new(),
+            fallback: None,
+        }
+    }
+}
+
+// parse an Accept header into media ranges, a missing or unparsable q value counts as 1, NaN
+// and infinities count as 0 (not acceptable), the rest is clamped to 0..=1
+fn parse_accept(header: &str) -> Vec<MediaRange> {
+    header
+        .split(',')
+        .enumerate()
+        .filter_map(|(position, item)| {
+            let mut params = item.split(';');
+            let media_type = params.next()?.trim();
+            if media_type.is_empty() {
+                return None;
+            }
+
+            let (kind, subtype) = split_media_type(media_type);
+            let q = params
+                .filter_map(|param| param.split_once('='))
+                .find(|(key, _)| key.trim().eq_ignore_ascii_case("q"))
+                .and_then(|(_, value)| value.trim().parse::<f32>().ok())
+                .map_or(1.0, |q| {
+                    if q.is_finite() {
+                        q.clamp(0.0, 1.0)
+                    } else {
+                        0.0
+                    }
+                });
+
+            Some(MediaRange {
+                kind,
+                subtype,
+                q,
+                position,
+            })
+        })
+        .collect()
+}
+
+// "Text/HTML" becomes ("text", "html"), a bare "*" is treated as "*/*"
+fn split_media_type(media_type: &str) -> (String, String) {
+    let media_type = media_type.split(';').next().unwrap_or("").trim();
+    let (kind, subtype) = media_type.split_once('/').unwrap_or((media_type, "*"));
+    (
+        kind.trim().to_ascii_lowercase(),
+        subtype.trim().to_ascii_lowercase(),
+    )
+}
@@ -0,0 +1,260 @@
+// src/range.rs
+
+// dependencies
+use crate::body::ResponseBody;
+use crate::response::AppResponse;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// more ranges than this in one request are ignored and the whole body is sent, so a client
+// can't make the server send the same bytes over and over
+const MAX_RANGES: usize = 16;
+
+// enum type to represent what a request's Range header asks for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum RangeRequest {
+    // no (usable) Range header, or an If-Range which doesn't match, send everything
+    Full,
+    // inclusive start and end offsets, in the order they were asked for
+    Partial(Vec<(u64, u64)>),
+    // none of the ranges overlap the body, answer 416
+    Unsatisfiable,
+}
+
+// one piece of a partial response body, the multipart headers or a slice of the content
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Segment {
+    Bytes(Vec<u8>),
+    Content { start: u64, len: u64 },
+}
+
+// the ranges a request asks for out of a body of len bytes, an If-Range header must match
+// the strong etag or the exact Last-Modified date, otherwise the whole body is sent
+pub(crate) fn requested_ranges(
+    headers: &HashMap<String, String>,
+    len: u64,
+    etag: Option<&str>,
+    last_modified: Option<&str>,
+) -> RangeRequest {
+    let Some(range) = headers.get("range") else {
+        return RangeRequest::Full;
+    };
+
+    if let Some(if_range) = headers.get("if-range").map(|value| value.trim()) {
+        let matches = if if_range.starts_with('"') || if_range.starts_with("W/") {
+            // weak etags never match for ranges
+            etag.is_some_and(|etag| !etag.starts_with("W/") && etag == if_range)
+        } else {
+            last_modified.is_some_and(|date| date == if_range)
+        };
+        if !matches {
+            return RangeRequest::Full;
+        }
+    }
+
+    parse_range(range, len)
+}
+
+// parse "bytes=0-99,200-,-50", anything malformed means the header is ignored
+fn parse_range(header: &str, len: u64) -> RangeRequest {
+    let Some((unit, specs)) = header.split_once('=') else {
+        return RangeRequest::Full;
+    };
+    if !unit.trim().eq_ignore_ascii_case("bytes") {
+        return RangeRequest::Full;
+    }
+
+    let mut ranges = Vec::new();
+    let mut count = 0;
+
+    for spec in specs.split(',').map(|spec| spec.trim()) {
+        if spec.is_empty() {
+            continue;
+        }
+        count += 1;
+
+        let Some((start, end)) = spec.split_once('-') else {
+            return RangeRequest::Full;
+        };
+        let (start, end) = (start.trim(), end.trim());
+
+        let range = if start.is_empty() {
+            // the last n bytes
+            let Ok(suffix) = end.parse::<u64>() else {
+                return RangeRequest::Full;
+            };
+            (suffix > 0 && len > 0).then(|| (len - suffix.min(len), len - 1))
+        } else {
+            let Ok(start) = start.parse::<u64>() else {
+                return RangeRequest::Full;
+            };
+            let end = if end.is_empty() {
+                u64::MAX
+            } else {
+                match end.parse::<u64>() {
+                    Ok(end) if end >= start => end,
+                    _ => return RangeRequest::Full,
+                }
+            };
+            (start < len).then(|| (start, end.min(len - 1)))
+        };
+
+        ranges.extend(range);
+    }
+
+    if count == 0 || count > MAX_RANGES {
+        RangeRequest::Full
+    } else if ranges.is_empty() {
+        RangeRequest::Unsatisfiable
+    } else {
+        RangeRequest::Partial(ranges)
+    }
+}
+
+// the 416 sent when no range can be satisfied
+pub(crate) fn unsatisfiable(len: u64) -> AppResponse {
+    AppResponse::new(416, "Range Not Satisfiable")
+        .with_header("Content-Type", "text/plain")
+        .with_header("Content-Range", &format!("bytes */{}", len))
+        .with_header("Accept-Ranges", "bytes")
+}
+
+// struct type to represent the body of a 206 response, a single range is sent as is with a
+// Content-Range header, several are wrapped in multipart/byteranges
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) struct PartialBody {
+    pub(crate) content_type: String,
+    pub(crate) content_range: Option<String>,
+    pub(crate) segments: Vec<Segment>,
+    pub(crate) len: u64,
+}
+
+// methods for the PartialBody type
+impl PartialBody {
+    pub(crate) fn new(ranges: &[(u64, u64)], total: u64, content_type: &str) -> Self {
+        if let [(start, end)] = ranges {
+            return PartialBody {
+                content_type: content_type.to_string(),
+                content_range: Some(format!("bytes {}-{}/{}", start, end, total)),
+                segments: vec![Segment::Content {
+                    start: *start,
+                    len: end - start + 1,
+                }],
+                len: end - start + 1,
+            };
+        }
+
+        let boundary = boundary();
+        let mut segments = Vec::new();
+        let mut len = 0;
+
+        for (start, end) in ranges {
+            let head = format!(
+                "--{}\r\nContent-Type: {}\r\nContent-Range: bytes {}-{}/{}\r\n\r\n",
+                boundary, content_type, start, end, total
+            );
+            len += head.len() as u64 + (end - start + 1) + 2;
+            segments.push(Segment::Bytes(head.into_bytes()));
+            segments.push(Segment::Content {
+                start: *start,
+                len: end - start + 1,
+            });
+            segments.push(Segment::Bytes(b"\r\n".to_vec()));
+        }
+
+        let tail = format!("--{}--\r\n", boundary);
+        len += tail.len() as u64;
+        segments.push(Segment::Bytes(tail.into_bytes()));
+
+        PartialBody {
+            content_type: format!("multipart/byteranges; boundary={}", boundary),
+            content_range: None,
+            segments,
+            len,
+        }
+    }
+
+    // turn a response into a 206 with this body's headers, keeping its other headers and cookies
+    pub(crate) fn into_response(
+        self,
+        mut response: AppResponse,
+        body: ResponseBody,
+    ) -> AppResponse {
+        response.status = hyper::StatusCode::PARTIAL_CONTENT;
+        response.body = body;
+        response.headers.retain(|key, _| {
+            !key.eq_ignore_ascii_case("content-type") && !key.eq_ignore_ascii_case("content-length")
+        });
+
+        let response = response
+            .with_header("Content-Type", &self.content_type)
+            .with_header("Accept-Ranges", "bytes");
+        match &self.content_range {
+            Some(content_range) => response.with_header("Content-Range", content_range),
+            None => response,
+        }
+    }
+}
+
+// answer a Range request against a buffered 200 response, other responses are passed through,
+// every buffered 200 advertises Accept-Ranges unless the handler set it already
+pub(crate) fn apply_to_bytes(
+    headers: &HashMap<String, String>,
+    response: AppResponse,
+) -> AppResponse {
+    if response.status != 200 || response.header("content-range").is_some() {
+        return response;
+    }
+    let ResponseBody::Bytes(bytes) = &response.body else {
+        return response;
+    };
+    if response.header("accept-ranges").is_some() {
+        // a handler which set Accept-Ranges: none opts out
+        return response;
+    }
+
+    let total = bytes.len() as u64;
+    let ranges = requested_ranges(
+        headers,
+        total,
+        response.header("etag"),
+        response.header("last-modified"),
+    );
+
+    let ranges = match ranges {
+        RangeRequest::Full => return response.with_header("Accept-Ranges", "bytes"),
+        RangeRequest::Unsatisfiable => return unsatisfiable(total),
+        RangeRequest::Partial(ranges) => ranges,
+    };
+
+    let content_type = response
+        .header("content-type")
+        .unwrap_or("application/octet-stream");
+    let partial = PartialBody::new(&ranges, total, content_type);
+
+    let mut body = Vec::with_capacity(partial.len as usize);
+    for segment in &partial.segments {
+        match segment {
+            Segment::Bytes(data) => body.extend_from_slice(data),
+            Segment::Content { start, len } => {
+                body.extend_from_slice(&bytes[*start as usize..(start + len) as usize])
+            }
+        }
+    }
+
+    partial.into_response(response, ResponseBody::Bytes(body))
+}
+
+// a multipart boundary which won't appear in the content by accident
+fn boundary() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+
+    let nanos = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_nanos() as u64)
+        .unwrap_or(0);
+    let count = COUNTER.fetch_add(1, Ordering::Relaxed);
+
+    format!("flux-web-range-{:016x}{:08x}", nanos, count)
+}
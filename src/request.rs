@@ -1,13 +1,196 @@
 // src/request.rs
 
 // dependencies
+use crate::body::RequestBody;
+use crate::cookie::parse_cookie_header;
 use crate::method::Method;
+use crate::version::Version;
 use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
 
 // struct type to represent a flux-web request
 pub struct AppRequest {
     pub method: Method,
+    pub uri: String,
+    pub scheme: String,
+    pub host: Option<String>,
+    pub version: Version,
+    // the address of the connection, a proxy's when there is one in front of the app
+    pub remote_addr: SocketAddr,
+    // the address of the client, from Forwarded or X-Forwarded-For when proxies are trusted
+    pub client_ip: IpAddr,
     pub headers: HashMap<String, String>,
     pub path: String,
+    // the values of the matched route's :name segments, filled in once the route is found
+    pub params: HashMap<String, String>,
     pub body: Vec<u8>,
-}
\ No newline at end of file
+    // typed values attached to the request, e.g. by middleware for the handlers after it
+    pub extensions: hyper::http::Extensions,
+    pub(crate) body_stream: Option<RequestBody>,
+}
+
+// methods for the AppRequest type
+impl AppRequest {
+    // the raw query string, without the leading '?'
+    pub fn query_string(&self) -> Option<&str> {
+        self.uri.split_once('?').map(|(_, query)| query)
+    }
+
+    // the body as a stream of chunks, on routes added with post_stream or put_stream this
+    // reads straight from the connection, elsewhere it yields the already collected body
+    pub fn body_stream(&mut self) -> RequestBody {
+        self.body_stream
+            .take()
+            .unwrap_or_else(|| RequestBody::buffered(std::mem::take(&mut self.body)))
+    }
+
+    // the media type of the body without parameters, lowercased, e.g. "application/json"
+    pub fn media_type(&self) -> Option<String> {
+        self.headers
+            .get("content-type")
+            .and_then(|value| value.split(';').next())
+            .map(|essence| essence.trim().to_ascii_lowercase())
+            .filter(|essence| !essence.is_empty())
+    }
+
+    // all cookies sent with the request, keyed by name
+    pub fn cookies(&self) -> HashMap<String, String> {
+        self.headers
+            .get("cookie")
+            .map(|header| parse_cookie_header(header))
+            .unwrap_or_default()
+    }
+
+    // the value of a path parameter, e.g. req.param("id") is "42" for /users/42 on the route
+    // "/users/:id"
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params.get(name).map(|value| value.as_str())
+    }
+
+    // the value of a single cookie sent with the request
+    pub fn cookie(&self, name: &str) -> Option<String> {
+        self.cookies().remove(name)
+    }
+
+    // a copy of the request for handlers which need to change it, a streamed body isn't copied
+    pub(crate) fn copy(&self) -> AppRequest {
+        AppRequest {
+            method: self.method.clone(),
+            uri: self.uri.clone(),
+            scheme: self.scheme.clone(),
+            host: self.host.clone(),
+            version: self.version,
+            remote_addr: self.remote_addr,
+            client_ip: self.client_ip,
+            headers: self.headers.clone(),
+            path: self.path.clone(),
+            params: self.params.clone(),
+            body: self.body.clone(),
+            extensions: self.extensions.clone(),
+            body_stream: None,
+        }
+    }
+
+    // the absolute URL of the request, rebuilt from scheme, host, path and query,
+    // useful for building links and callbacks that point back at this server
+    pub fn url(&self) -> String {
+        let host = self.host.as_deref().unwrap_or("localhost");
+        match self.query_string() {
+            Some(query) => format!("{}://{}{}?{}", self.scheme, host, self.path, query),
+            None => format!("{}://{}{}", self.scheme, host, self.path),
+        }
+    }
+}
+
+// work out the scheme the client used, honouring proxy headers only when they are trusted
+pub(crate) fn resolve_scheme(
+    uri: &hyper::Uri,
+    headers: &HashMap<String, String>,
+    trust_proxy: bool,
+) -> String {
+    if trust_proxy {
+        if let Some(proto) = forwarded_param(headers, "proto") {
+            return proto.to_ascii_lowercase();
+        }
+        if let Some(proto) = first_value(headers, "x-forwarded-proto") {
+            return proto.to_ascii_lowercase();
+        }
+    }
+
+    uri.scheme_str().unwrap_or("http").to_ascii_lowercase()
+}
+
+// work out the host (and port) the client addressed, honouring proxy headers only when they are trusted
+pub(crate) fn resolve_host(
+    uri: &hyper::Uri,
+    headers: &HashMap<String, String>,
+    trust_proxy: bool,
+) -> Option<String> {
+    if trust_proxy {
+        if let Some(host) = forwarded_param(headers, "host") {
+            return Some(host);
+        }
+        if let Some(host) = first_value(headers, "x-forwarded-host") {
+            return Some(host);
+        }
+    }
+
+    // absolute-form requests and HTTP/2 carry the authority in the URI itself
+    uri.authority()
+        .map(|authority| authority.to_string())
+        .or_else(|| headers.get("host").filter(|h| !h.is_empty()).cloned())
+}
+
+// work out the client's address, honouring proxy headers only when they are trusted
+pub(crate) fn resolve_client_ip(
+    remote_addr: SocketAddr,
+    headers: &HashMap<String, String>,
+    trust_proxy: bool,
+) -> IpAddr {
+    if trust_proxy {
+        let forwarded = forwarded_param(headers, "for")
+            .or_else(|| first_value(headers, "x-forwarded-for"))
+            .and_then(|value| parse_node(&value));
+        if let Some(ip) = forwarded {
+            return ip;
+        }
+    }
+
+    remote_addr.ip()
+}
+
+// an address such as 192.0.2.60, 192.0.2.60:47011 or [2001:db8::1]:4711, "unknown" and
+// obfuscated identifiers give None
+fn parse_node(value: &str) -> Option<IpAddr> {
+    if let Some(bracketed) = value.strip_prefix('[') {
+        return bracketed.split(']').next()?.parse().ok();
+    }
+    value
+        .parse()
+        .ok()
+        .or_else(|| value.parse::<SocketAddr>().ok().map(|addr| addr.ip()))
+}
+
+// first entry of a comma separated header such as X-Forwarded-Proto: https, http
+fn first_value(headers: &HashMap<String, String>, name: &str) -> Option<String> {
+    headers
+        .get(name)
+        .and_then(|value| value.split(',').next())
+        .map(|value| value.trim().to_string())
+        .filter(|value| !value.is_empty())
+}
+
+// a single parameter from the first element of an RFC 7239 Forwarded header
+fn forwarded_param(headers: &HashMap<String, String>, param: &str) -> Option<String> {
+    let first = headers.get("forwarded")?.split(',').next()?;
+
+    first.split(';').find_map(|pair| {
+        let (key, value) = pair.split_once('=')?;
+        if key.trim().eq_ignore_ascii_case(param) {
+            let value = value.trim().trim_matches('"');
+            (!value.is_empty()).then(|| value.to_string())
+        } else {
+            None
+        }
+    })
+}
@@ -1,31 +1,64 @@
 // src/response.rs
 
 // dependencies
+use crate::body::ResponseBody;
+use crate::cookie::Cookie;
+use crate::request::AppRequest;
+use futures_core::Stream;
+use hyper::body::Bytes;
+use hyper::header::{HeaderName, HeaderValue};
+use hyper::{Response, StatusCode};
 use std::collections::HashMap;
 
 // struct type to represent a flux-web response
 pub struct AppResponse {
-    pub status: u16,
+    pub status: StatusCode,
     pub headers: HashMap<String, String>,
-    pub body: Option<Vec<u8>>,
+    pub body: ResponseBody,
+    pub cookies: Vec<Cookie>,
+    // the first invalid status or header a handler tried to set, sent as a 500 instead
+    error: Option<ResponseError>,
 }
 
+// enum type to represent a response which can't be sent as it stands
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ResponseError {
+    // not a status code between 100 and 999
+    InvalidStatus(u16),
+    // a header name with characters that aren't allowed, e.g. spaces or ':'
+    InvalidHeaderName(String),
+    // a header value containing a line break or other control character
+    InvalidHeaderValue(String),
+    // a cookie which would change its own attributes or break the Set-Cookie header, see
+    // Cookie::is_valid, with the cookie's name
+    InvalidCookie(String),
+}
+
+// implement the Display trait for the ResponseError type
+impl std::fmt::Display for ResponseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ResponseError::InvalidStatus(code) => write!(f, "invalid status code {}", code),
+            ResponseError::InvalidHeaderName(name) => write!(f, "invalid header name {:?}", name),
+            ResponseError::InvalidHeaderValue(name) => {
+                write!(f, "invalid value for header {:?}", name)
+            }
+            ResponseError::InvalidCookie(name) => write!(f, "invalid cookie {:?}", name),
+        }
+    }
+}
+
+// implement the Error trait for the ResponseError type
+impl std::error::Error for ResponseError {}
+
 // methods for the AppResponse type
 impl AppResponse {
     pub fn new(status: u16, body: impl Into<String>) -> Self {
-        AppResponse {
-            status,
-            headers: HashMap::new(),
-            body: Some(body.into().into_bytes()),
-        }
+        Self::build(status, ResponseBody::Bytes(body.into().into_bytes()))
     }
 
     pub fn status(code: u16) -> Self {
-        AppResponse {
-            status: code,
-            headers: HashMap::new(),
-            body: None,
-        }
+        Self::build(code, ResponseBody::Empty)
     }
 
     pub fn ok(body: impl Into<String>) -> Self {
@@ -37,11 +70,7 @@ impl AppResponse {
     }
 
     pub fn no_content() -> Self {
-        AppResponse {
-            status: 204,
-            headers: HashMap::new(),
-            body: None,
-        }
+        Self::build(204, ResponseBody::Empty)
     }
 
     pub fn bad_request(body: impl Into<String>) -> Self {
@@ -56,16 +85,202 @@ impl AppResponse {
         Self::new(500, body)
     }
 
+    // 302 Found, the client repeats the request at the new location (usually as a GET)
+    pub fn redirect(location: &str) -> Self {
+        Self::status(302).with_header("Location", location)
+    }
+
+    // 301 Moved Permanently
+    pub fn permanent_redirect(location: &str) -> Self {
+        Self::status(301).with_header("Location", location)
+    }
+
+    // 303 See Other, the client fetches the new location with a GET, e.g. after a form POST
+    pub fn see_other(location: &str) -> Self {
+        Self::status(303).with_header("Location", location)
+    }
+
+    // 307 Temporary Redirect, the client repeats the request with the same method and body
+    pub fn temporary_redirect(location: &str) -> Self {
+        Self::status(307).with_header("Location", location)
+    }
+
+    // 308 Permanent Redirect, like 301 but the method and body must be kept
+    pub fn permanent_redirect_308(location: &str) -> Self {
+        Self::status(308).with_header("Location", location)
+    }
+
+    // refuse a redirect whose Location points at another host with a 400, use this when the
+    // location comes from user input (e.g. ?next=...) so the app can't be used as an open redirect
+    pub fn same_host_only(self, req: &AppRequest) -> Self {
+        let location = self
+            .headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case("location"))
+            .map(|(_, value)| value);
+
+        match location {
+            Some(location) if !is_same_host(req, location) => {
+                AppResponse::bad_request("Refusing to redirect to another host")
+                    .with_header("Content-Type", "text/plain")
+            }
+            _ => self,
+        }
+    }
+
     pub fn with_bytes(status: u16, bytes: Vec<u8>) -> Self {
+        Self::build(status, ResponseBody::Bytes(bytes))
+    }
+
+    // a response whose body is sent chunk by chunk as the stream produces it, for large
+    // exports or slow generators that shouldn't be built up in memory first
+    pub fn stream(
+        status: u16,
+        stream: impl Stream<Item = std::io::Result<Bytes>> + Send + 'static,
+    ) -> Self {
+        Self::build(status, ResponseBody::stream(stream))
+    }
+
+    // set a header, replacing any earlier value, a name or value which isn't allowed in
+    // HTTP (such as a value with a line break in it) is dropped and the response becomes a 500
+    pub fn with_header(mut self, key: &str, value: &str) -> Self {
+        if HeaderName::from_bytes(key.as_bytes()).is_err() {
+            self.error
+                .get_or_insert(ResponseError::InvalidHeaderName(key.to_string()));
+        } else if HeaderValue::from_str(value).is_err() {
+            self.error
+                .get_or_insert(ResponseError::InvalidHeaderValue(key.to_string()));
+        } else {
+            self.headers.insert(key.to_string(), value.to_string());
+        }
+        self
+    }
+
+    // the value of a header set on this response, the name is matched case-insensitively
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+
+    // add a header name to Vary, keeping whatever was there already
+    pub(crate) fn add_vary(mut self, name: &str) -> Self {
+        let vary = match self.header("vary") {
+            Some(vary)
+                if vary
+                    .split(',')
+                    .any(|item| item.trim().eq_ignore_ascii_case(name)) =>
+            {
+                return self;
+            }
+            Some(vary) => format!("{}, {}", vary, name),
+            None => name.to_string(),
+        };
+
+        // drop the old entry first, it may be spelled differently
+        self.headers
+            .retain(|key, _| !key.eq_ignore_ascii_case("vary"));
+        self.with_header("Vary", &vary)
+    }
+
+    // add a Set-Cookie header, unlike with_header this can be called once per cookie, an
+    // invalid cookie (see Cookie::is_valid) is dropped and the response becomes a 500
+    pub fn with_cookie(mut self, cookie: Cookie) -> Self {
+        if cookie.is_valid() {
+            self.cookies.push(cookie);
+        } else {
+            self.error
+                .get_or_insert(ResponseError::InvalidCookie(cookie.name));
+        }
+        self
+    }
+
+    // convert into a hyper response, failing instead of panicking on an invalid status,
+    // header or cookie
+    pub fn into_hyper(self) -> Result<Response<ResponseBody>, ResponseError> {
+        if let Some(err) = self.error {
+            return Err(err);
+        }
+
+        let mut response = Response::new(self.body);
+        *response.status_mut() = self.status;
+
+        let headers = response.headers_mut();
+        for (key, value) in &self.headers {
+            let name = HeaderName::from_bytes(key.as_bytes())
+                .map_err(|_| ResponseError::InvalidHeaderName(key.clone()))?;
+            let value = HeaderValue::from_str(value)
+                .map_err(|_| ResponseError::InvalidHeaderValue(key.clone()))?;
+            headers.insert(name, value);
+        }
+
+        // cookies may have been pushed onto the cookies field directly
+        for cookie in &self.cookies {
+            let value = Some(cookie)
+                .filter(|cookie| cookie.is_valid())
+                .and_then(|cookie| HeaderValue::from_str(&cookie.to_string()).ok())
+                .ok_or_else(|| ResponseError::InvalidCookie(cookie.name.clone()))?;
+            headers.append(hyper::header::SET_COOKIE, value);
+        }
+
+        Ok(response)
+    }
+
+    fn build(status: u16, body: ResponseBody) -> Self {
+        let (status, error) = match StatusCode::from_u16(status) {
+            Ok(status) => (status, None),
+            Err(_) => (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                Some(ResponseError::InvalidStatus(status)),
+            ),
+        };
+
         AppResponse {
             status,
             headers: HashMap::new(),
-            body: Some(bytes),
+            body,
+            cookies: Vec::new(),
+            error,
         }
     }
+}
 
-    pub fn with_header(mut self, key: &str, value: &str) -> Self {
-        self.headers.insert(key.to_string(), value.to_string());
-        self
+// a relative location stays on this host, an absolute one must name the same host
+fn is_same_host(req: &AppRequest, location: &str) -> bool {
+    // browsers drop tabs and line breaks from URLs and treat '\' like '/', so
+    // "/\evil.example" and "/\t/evil.example" are protocol-relative too
+    let normalized: String = location
+        .trim()
+        .chars()
+        .filter(|c| !matches!(c, '\t' | '\r' | '\n'))
+        .map(|c| if c == '\\' { '/' } else { c })
+        .collect();
+
+    if normalized.starts_with("//") {
+        return false;
+    }
+
+    // a ':' before the path, query or fragment starts means there is a scheme
+    let path_start = normalized.find(['/', '?', '#']).unwrap_or(normalized.len());
+    let Some(colon) = normalized.find(':').filter(|&colon| colon < path_start) else {
+        return true;
+    };
+
+    let scheme = &normalized[..colon];
+    if !scheme.eq_ignore_ascii_case("http") && !scheme.eq_ignore_ascii_case("https") {
+        return false;
+    }
+
+    let authority = normalized[colon + 1..]
+        .strip_prefix("//")
+        .and_then(|rest| rest.split(['/', '?', '#']).next())
+        .unwrap_or("");
+    // drop any userinfo, "https://app.example@evil.example" goes to evil.example
+    let host = authority.rsplit('@').next().unwrap_or(authority);
+
+    match &req.host {
+        Some(own_host) => !host.is_empty() && host.eq_ignore_ascii_case(own_host),
+        None => false,
     }
 }
@@ -1,15 +1,64 @@
 // src/lib/router.rs
 
 // dependencies
-use crate::handler::Handler;
+#[cfg(feature = "websocket")]
+use crate::handler::WsHandler;
+use crate::handler::{Handler, StreamHandler};
 use crate::method::Method;
+use crate::middleware::Middleware;
+use crate::static_files::percent_decode;
+use std::collections::HashMap;
+use std::sync::Arc;
 
+// enum type to represent the handler of a route, either a plain handler which gets the
+// collected body, a streaming handler which reads the body itself, or a WebSocket handler
+// which takes over the connection after the upgrade
+pub enum RouteHandler {
+    Buffered(Box<dyn Handler + Send + Sync>),
+    Streaming(Box<dyn StreamHandler>),
+    #[cfg(feature = "websocket")]
+    WebSocket(Arc<dyn WsHandler>),
+}
 
-// struct type to represent a route, which consists of a method, path, and handler
+// struct type to represent a route, which consists of a method, path, and handler, a path
+// segment starting with ':' is a parameter which matches any segment, e.g. "/users/:id"
 pub struct Route {
     pub method: Method,
     pub path: String,
-    pub handler: Box<dyn Handler + Send + Sync>,
+    pub handler: RouteHandler,
+}
+
+// methods for the Route type
+impl Route {
+    // the values of the route's parameters in a request path, percent-decoded, None when the
+    // path doesn't match, e.g. "/users/:id" gives {"id": "42"} for /users/42
+    pub fn params(&self, path: &str) -> Option<HashMap<String, String>> {
+        let mut pattern = self.path.split('/');
+        let mut segments = path.split('/');
+        let mut params = HashMap::new();
+
+        loop {
+            match (pattern.next(), segments.next()) {
+                (None, None) => return Some(params),
+                (Some(expected), Some(segment)) => match expected.strip_prefix(':') {
+                    Some(name) if !segment.is_empty() => {
+                        params.insert(name.to_string(), percent_decode(segment)?);
+                    }
+                    Some(_) => return None,
+                    None if expected != segment => return None,
+                    None => {}
+                },
+                _ => return None,
+            }
+        }
+    }
+
+    // the names of the route's parameters, in the order they appear in its path
+    pub fn param_names(&self) -> impl Iterator<Item = &str> {
+        self.path
+            .split('/')
+            .filter_map(|segment| segment.strip_prefix(':'))
+    }
 }
 
 // implement the Debug trait for the Route type
@@ -23,26 +72,93 @@ impl std::fmt::Debug for Route {
     }
 }
 
-// struct type which represents a Router, a vector collection of Routes
-#[derive(Debug)]
+// struct type which represents a Router, a vector collection of Routes, a Router built on its
+// own can be mounted under a prefix with App::mount
+#[derive(Debug, Default)]
 pub struct Router {
     pub routes: Vec<Route>,
+    // middleware for the requests under the prefix the router is mounted at
+    pub(crate) middleware: Vec<Arc<dyn Middleware>>,
 }
 
-// methods for the Route type
+// methods for the Router type
 impl Router {
+    pub fn new() -> Self {
+        Router::default()
+    }
+
+    pub fn get(&mut self, path: &str, handler: impl Handler + 'static) -> &mut Self {
+        self.add_route(Method::Get, path, handler);
+        self
+    }
+
+    pub fn post(&mut self, path: &str, handler: impl Handler + 'static) -> &mut Self {
+        self.add_route(Method::Post, path, handler);
+        self
+    }
+
+    pub fn put(&mut self, path: &str, handler: impl Handler + 'static) -> &mut Self {
+        self.add_route(Method::Put, path, handler);
+        self
+    }
+
+    pub fn patch(&mut self, path: &str, handler: impl Handler + 'static) -> &mut Self {
+        self.add_route(Method::Patch, path, handler);
+        self
+    }
+
+    pub fn delete(&mut self, path: &str, handler: impl Handler + 'static) -> &mut Self {
+        self.add_route(Method::Delete, path, handler);
+        self
+    }
+
+    // run middleware around every request under the prefix the router is mounted at, once
+    // mounted it works like app.use_at(prefix, middleware) added at that point
+    pub fn use_middleware(&mut self, middleware: impl Middleware + 'static) -> &mut Self {
+        self.middleware.push(Arc::new(middleware));
+        self
+    }
+
     pub fn add_route(&mut self, method: Method, path: &str, handler: impl Handler + 'static) {
         self.routes.push(Route {
             method,
             path: path.to_string(),
-            handler: Box::new(handler),
+            handler: RouteHandler::Buffered(Box::new(handler)),
+        });
+    }
+
+    pub fn add_stream_route(
+        &mut self,
+        method: Method,
+        path: &str,
+        handler: impl StreamHandler + 'static,
+    ) {
+        self.routes.push(Route {
+            method,
+            path: path.to_string(),
+            handler: RouteHandler::Streaming(Box::new(handler)),
         });
     }
 
-    pub fn find_route(&self, method: &Method, path: &str) -> Option<&(dyn Handler + Send + Sync)> {
-        self.routes
-            .iter()
-            .find(|route| route.method == *method && route.path == path)
-            .map(|route| route.handler.as_ref())
+    #[cfg(feature = "websocket")]
+    pub fn add_ws_route(&mut self, path: &str, handler: impl WsHandler + 'static) {
+        self.routes.push(Route {
+            method: Method::Get,
+            path: path.to_string(),
+            handler: RouteHandler::WebSocket(Arc::new(handler)),
+        });
     }
-}
\ No newline at end of file
+
+    pub fn find_route(&self, method: &Method, path: &str) -> Option<&RouteHandler> {
+        self.find(method, path).map(|route| &route.handler)
+    }
+
+    // the route a request matches, with its path pattern, a route with exactly this path wins
+    // over the parameter routes, among those the first one added wins
+    pub fn find(&self, method: &Method, path: &str) -> Option<&Route> {
+        let routes = || self.routes.iter().filter(|route| route.method == *method);
+        routes()
+            .find(|route| route.path == path)
+            .or_else(|| routes().find(|route| route.params(path).is_some()))
+    }
+}
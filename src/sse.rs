@@ -0,0 +1,290 @@
+// src/sse.rs
+
+// dependencies
+use crate::request::AppRequest;
+use crate::response::AppResponse;
+use futures_core::Stream;
+use hyper::body::Bytes;
+use std::collections::VecDeque;
+use std::io;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::sync::broadcast;
+use tokio::time::{Instant, Sleep};
+
+// how often a comment is sent on an idle stream unless configured otherwise
+const DEFAULT_KEEP_ALIVE: Duration = Duration::from_secs(15);
+
+// struct type to represent a single Server-Sent Event
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Event {
+    pub id: Option<String>,
+    pub event: Option<String>,
+    pub data: String,
+    pub retry: Option<Duration>,
+}
+
+// methods for the Event type
+impl Event {
+    pub fn new(data: impl Into<String>) -> Self {
+        Event {
+            data: data.into(),
+            ..Event::default()
+        }
+    }
+
+    // the event type, clients listen for it with addEventListener(name, ...)
+    pub fn event(mut self, event: impl Into<String>) -> Self {
+        self.event = Some(event.into());
+        self
+    }
+
+    // the id the client sends back in Last-Event-ID when it reconnects
+    pub fn id(mut self, id: impl Into<String>) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+
+    // how long the client waits before reconnecting after the stream drops
+    pub fn retry(mut self, retry: Duration) -> Self {
+        self.retry = Some(retry);
+        self
+    }
+
+    // the event in the text/event-stream wire format, multi-line data becomes several
+    // data: lines, and line breaks are stripped from the single-line fields
+    pub fn to_frame(&self) -> String {
+        let mut frame = String::new();
+
+        if let Some(event) = &self.event {
+            frame.push_str(&format!("event: {}\n", single_line(event)));
+        }
+        if let Some(id) = &self.id {
+            frame.push_str(&format!("id: {}\n", single_line(id)));
+        }
+        if let Some(retry) = self.retry {
+            frame.push_str(&format!("retry: {}\n", retry.as_millis()));
+        }
+        // clients end a line at CRLF, a lone CR or a lone LF, so each gets its own data: line,
+        // otherwise "a\rid: 9" would set the event's id
+        for line in self
+            .data
+            .split("\r\n")
+            .flat_map(|line| line.split(['\r', '\n']))
+        {
+            frame.push_str(&format!("data: {}\n", line));
+        }
+
+        frame.push('\n');
+        frame
+    }
+}
+
+// struct type to represent a Server-Sent Events response built from a stream of events
+pub struct Sse {
+    events: Pin<Box<dyn Stream<Item = Event> + Send>>,
+    keep_alive: Option<Duration>,
+}
+
+// methods for the Sse type
+impl Sse {
+    pub fn new(events: impl Stream<Item = Event> + Send + 'static) -> Self {
+        Sse {
+            events: Box::pin(events),
+            keep_alive: Some(DEFAULT_KEEP_ALIVE),
+        }
+    }
+
+    // send a comment after this long without an event, so proxies don't close the connection
+    pub fn keep_alive(mut self, interval: Duration) -> Self {
+        self.keep_alive = Some(interval);
+        self
+    }
+
+    // never send keep-alive comments
+    pub fn without_keep_alive(mut self) -> Self {
+        self.keep_alive = None;
+        self
+    }
+}
+
+// implement the From trait to turn an Sse into a streaming AppResponse
+impl From<Sse> for AppResponse {
+    fn from(sse: Sse) -> Self {
+        let stream = SseStream {
+            events: sse.events,
+            timer: sse
+                .keep_alive
+                .map(|interval| (interval, Box::pin(tokio::time::sleep(interval)))),
+        };
+
+        AppResponse::stream(200, stream)
+            .with_header("Content-Type", "text/event-stream")
+            .with_header("Cache-Control", "no-cache")
+            // stop nginx and similar proxies from buffering the stream
+            .with_header("X-Accel-Buffering", "no")
+    }
+}
+
+// SSE methods for the AppResponse type
+impl AppResponse {
+    // a Server-Sent Events response with the default keep-alive interval
+    pub fn sse(events: impl Stream<Item = Event> + Send + 'static) -> Self {
+        Sse::new(events).into()
+    }
+}
+
+// the byte stream behind an SSE response, interleaving events with keep-alive comments
+struct SseStream {
+    events: Pin<Box<dyn Stream<Item = Event> + Send>>,
+    timer: Option<(Duration, Pin<Box<Sleep>>)>,
+}
+
+// implement the Stream trait for the SseStream type
+impl Stream for SseStream {
+    type Item = io::Result<Bytes>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        match this.events.as_mut().poll_next(cx) {
+            Poll::Ready(Some(event)) => {
+                if let Some((interval, sleep)) = &mut this.timer {
+                    sleep.as_mut().reset(Instant::now() + *interval);
+                }
+                return Poll::Ready(Some(Ok(Bytes::from(event.to_frame()))));
+            }
+            Poll::Ready(None) => return Poll::Ready(None),
+            Poll::Pending => {}
+        }
+
+        if let Some((interval, sleep)) = &mut this.timer
+            && sleep.as_mut().poll(cx).is_ready()
+        {
+            sleep.as_mut().reset(Instant::now() + *interval);
+            return Poll::Ready(Some(Ok(Bytes::from_static(b": keep-alive\n\n"))));
+        }
+
+        Poll::Pending
+    }
+}
+
+// struct type to represent a broadcast hub which many SSE clients can subscribe to, it keeps the
+// most recent events so reconnecting clients can resume from their Last-Event-ID
+#[derive(Clone)]
+pub struct SseHub {
+    inner: Arc<HubInner>,
+}
+
+struct HubInner {
+    sender: broadcast::Sender<(u64, Event)>,
+    // the next id and the replay history, locked together so a subscriber never misses or
+    // duplicates an event published while it is joining
+    state: Mutex<HubState>,
+    history_size: usize,
+}
+
+struct HubState {
+    next_id: u64,
+    history: VecDeque<(u64, Event)>,
+}
+
+// methods for the SseHub type
+impl SseHub {
+    // a hub which keeps the last history_size events for resuming clients
+    pub fn new(history_size: usize) -> Self {
+        let (sender, _) = broadcast::channel(history_size.max(16));
+
+        SseHub {
+            inner: Arc::new(HubInner {
+                sender,
+                state: Mutex::new(HubState {
+                    next_id: 1,
+                    history: VecDeque::with_capacity(history_size),
+                }),
+                history_size,
+            }),
+        }
+    }
+
+    // send an event to every subscriber, the hub numbers events itself (overriding any id
+    // that was set) so it can tell which ones a resuming client has missed
+    pub fn publish(&self, event: Event) -> u64 {
+        let mut state = self.inner.state.lock().unwrap();
+
+        let id = state.next_id;
+        state.next_id += 1;
+        let event = event.id(id.to_string());
+
+        if self.inner.history_size > 0 {
+            if state.history.len() == self.inner.history_size {
+                state.history.pop_front();
+            }
+            state.history.push_back((id, event.clone()));
+        }
+
+        // an error only means nobody is listening right now
+        let _ = self.inner.sender.send((id, event));
+        id
+    }
+
+    // the number of clients currently subscribed
+    pub fn subscriber_count(&self) -> usize {
+        self.inner.sender.receiver_count()
+    }
+
+    // a stream of events after last_event_id (from the history) followed by live events,
+    // a subscriber that falls too far behind is disconnected so it can resume from history
+    pub fn subscribe(&self, last_event_id: Option<&str>) -> impl Stream<Item = Event> + use<> {
+        let state = self.inner.state.lock().unwrap();
+
+        let missed: VecDeque<Event> =
+            match last_event_id.and_then(|id| id.trim().parse::<u64>().ok()) {
+                Some(last) => state
+                    .history
+                    .iter()
+                    .filter(|(id, _)| *id > last)
+                    .map(|(_, event)| event.clone())
+                    .collect(),
+                None => VecDeque::new(),
+            };
+        let receiver = self.inner.sender.subscribe();
+        drop(state);
+
+        futures_util::stream::unfold(
+            (missed, receiver),
+            |(mut missed, mut receiver)| async move {
+                if let Some(event) = missed.pop_front() {
+                    return Some((event, (missed, receiver)));
+                }
+                match receiver.recv().await {
+                    Ok((_, event)) => Some((event, (missed, receiver))),
+                    Err(_) => None,
+                }
+            },
+        )
+    }
+
+    // an SSE response subscribed to this hub, resuming from the request's Last-Event-ID
+    pub fn response(&self, req: &AppRequest) -> AppResponse {
+        let last_event_id = req.headers.get("last-event-id").map(|id| id.as_str());
+        AppResponse::sse(self.subscribe(last_event_id))
+    }
+}
+
+// implement the Debug trait for the SseHub type
+impl std::fmt::Debug for SseHub {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SseHub")
+            .field("subscribers", &self.subscriber_count())
+            .field("history_size", &self.inner.history_size)
+            .finish()
+    }
+}
+
+// ids and event names can't span lines in the wire format
+fn single_line(value: &str) -> String {
+    value.replace(['\r', '\n'], "")
+}
@@ -0,0 +1,258 @@
+// src/static_files.rs
+
+// dependencies
+use crate::body::ResponseBody;
+use crate::conditional::weak_etag;
+use crate::range::{self, PartialBody, RangeRequest, Segment};
+use crate::response::AppResponse;
+use hyper::body::Bytes;
+use std::collections::{HashMap, VecDeque};
+use std::io::{self, SeekFrom};
+use std::path::{Path, PathBuf};
+use tokio::fs::File;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+
+// how much of a file is read into memory at a time while it's being sent
+const CHUNK_SIZE: usize = 64 * 1024;
+
+// struct type to represent a directory of files served under a URL prefix, see App::static_dir
+#[derive(Debug, Clone)]
+pub(crate) struct StaticDir {
+    prefix: String,
+    root: PathBuf,
+}
+
+// methods for the StaticDir type
+impl StaticDir {
+    pub(crate) fn new(prefix: &str, root: impl Into<PathBuf>) -> Self {
+        StaticDir {
+            // "/assets/" and "assets" both mount at "/assets", "/" mounts at the root
+            prefix: format!("/{}", prefix.trim_matches('/')),
+            root: root.into(),
+        }
+    }
+
+    // the response for a request path, None when the path is outside the prefix or there
+    // is no such file, so the next directory (or the 404) gets a turn
+    pub(crate) async fn serve(
+        &self,
+        path: &str,
+        headers: &HashMap<String, String>,
+    ) -> Option<AppResponse> {
+        let rest = if self.prefix == "/" {
+            path
+        } else if path == self.prefix {
+            ""
+        } else {
+            path.strip_prefix(&self.prefix)?.strip_prefix('/')?
+        };
+
+        let relative = match safe_relative_path(rest) {
+            Ok(Some(relative)) => relative,
+            // a hidden file such as .env is treated as if it wasn't there
+            Ok(None) => return None,
+            Err(()) => return Some(forbidden()),
+        };
+
+        let mut file_path = self.root.join(relative);
+        let mut metadata = tokio::fs::metadata(&file_path).await.ok()?;
+
+        if metadata.is_dir() {
+            // relative links in an index page only work with the trailing slash
+            // (with the leading slashes collapsed, "//evil.example" would leave the host)
+            if !path.ends_with('/') {
+                let location = format!("/{}/", path.trim_start_matches('/'));
+                return Some(AppResponse::permanent_redirect(&location));
+            }
+            file_path = file_path.join("index.html");
+            metadata = tokio::fs::metadata(&file_path).await.ok()?;
+        }
+
+        if !metadata.is_file() {
+            return None;
+        }
+
+        // a symlink must not lead out of the directory either
+        let root = tokio::fs::canonicalize(&self.root).await.ok()?;
+        let canonical = tokio::fs::canonicalize(&file_path).await.ok()?;
+        if !canonical.starts_with(&root) {
+            return Some(forbidden());
+        }
+
+        let file = File::open(&canonical).await.ok()?;
+        let len = metadata.len();
+        let content_type = mime_type(&canonical);
+        let modified = metadata.modified().ok();
+        let last_modified = modified.map(httpdate::fmt_http_date);
+        let etag = modified.map(|modified| weak_etag(len, modified));
+
+        let mut response = match range::requested_ranges(
+            headers,
+            len,
+            etag.as_deref(),
+            last_modified.as_deref(),
+        ) {
+            RangeRequest::Full => AppResponse::stream(
+                200,
+                file_stream(file, vec![Segment::Content { start: 0, len }]),
+            )
+            .with_header("Content-Type", content_type)
+            .with_header("Content-Length", &len.to_string())
+            .with_header("Accept-Ranges", "bytes"),
+            RangeRequest::Unsatisfiable => return Some(range::unsatisfiable(len)),
+            RangeRequest::Partial(ranges) => {
+                let partial = PartialBody::new(&ranges, len, content_type);
+                let body_len = partial.len;
+                let body = ResponseBody::stream(file_stream(file, partial.segments.clone()));
+                partial
+                    .into_response(AppResponse::status(206), body)
+                    .with_header("Content-Length", &body_len.to_string())
+            }
+        };
+        if let Some(last_modified) = &last_modified {
+            response = response.with_header("Last-Modified", last_modified);
+        }
+        if let Some(etag) = &etag {
+            response = response.with_header("ETag", etag);
+        }
+
+        Some(response)
+    }
+}
+
+// the file's contents for each segment in turn, read in chunks as the client takes them
+fn file_stream(
+    file: File,
+    segments: Vec<Segment>,
+) -> impl futures_core::Stream<Item = io::Result<Bytes>> + Send {
+    // the file, where its cursor is, and the segments still to send
+    let state = Some((file, 0, VecDeque::from(segments)));
+
+    futures_util::stream::unfold(state, |state| async move {
+        let (mut file, mut position, mut segments) = state?;
+
+        loop {
+            match segments.pop_front()? {
+                Segment::Bytes(data) => {
+                    return Some((Ok(Bytes::from(data)), Some((file, position, segments))));
+                }
+                Segment::Content { len: 0, .. } => continue,
+                Segment::Content { start, len } => {
+                    if position != start {
+                        if let Err(err) = file.seek(SeekFrom::Start(start)).await {
+                            // end the stream after reporting the error
+                            return Some((Err(err), None));
+                        }
+                        position = start;
+                    }
+
+                    let mut buffer = vec![0; CHUNK_SIZE.min(len as usize)];
+                    let read = match file.read(&mut buffer).await {
+                        Ok(0) => Err(io::Error::from(io::ErrorKind::UnexpectedEof)),
+                        result => result,
+                    };
+                    let read = match read {
+                        Ok(read) => read,
+                        Err(err) => return Some((Err(err), None)),
+                    };
+
+                    buffer.truncate(read);
+                    position += read as u64;
+                    segments.push_front(Segment::Content {
+                        start: position,
+                        len: len - read as u64,
+                    });
+                    return Some((Ok(Bytes::from(buffer)), Some((file, position, segments))));
+                }
+            }
+        }
+    })
+}
+
+// decode the path below the prefix and check every segment, Err for anything which could
+// escape the directory (.., encoded slashes, backslashes, NUL) and Ok(None) for hidden files
+fn safe_relative_path(rest: &str) -> Result<Option<PathBuf>, ()> {
+    let mut relative = PathBuf::new();
+
+    for segment in rest.split('/').filter(|segment| !segment.is_empty()) {
+        let segment = percent_decode(segment).ok_or(())?;
+
+        if segment == ".." || segment.contains(['/', '\\', '\0', ':']) {
+            return Err(());
+        }
+        if segment.starts_with('.') {
+            return Ok(None);
+        }
+
+        relative.push(segment);
+    }
+
+    Ok(Some(relative))
+}
+
+// %XX decoding for a path segment, unlike form data '+' stays a '+', None for malformed input
+pub(crate) fn percent_decode(segment: &str) -> Option<String> {
+    let bytes = segment.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = std::str::from_utf8(bytes.get(i + 1..i + 3)?).ok()?;
+            decoded.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+
+    String::from_utf8(decoded).ok()
+}
+
+// the Content-Type for a file, by extension
+fn mime_type(path: &Path) -> &'static str {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or("")
+        .to_ascii_lowercase();
+
+    match extension.as_str() {
+        "html" | "htm" => "text/html; charset=utf-8",
+        "css" => "text/css; charset=utf-8",
+        "js" | "mjs" => "text/javascript; charset=utf-8",
+        "json" | "map" => "application/json",
+        "txt" => "text/plain; charset=utf-8",
+        "csv" => "text/csv; charset=utf-8",
+        "xml" => "application/xml",
+        "md" => "text/markdown; charset=utf-8",
+        "svg" => "image/svg+xml",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "webp" => "image/webp",
+        "avif" => "image/avif",
+        "ico" => "image/x-icon",
+        "woff" => "font/woff",
+        "woff2" => "font/woff2",
+        "ttf" => "font/ttf",
+        "otf" => "font/otf",
+        "wasm" => "application/wasm",
+        "pdf" => "application/pdf",
+        "zip" => "application/zip",
+        "gz" => "application/gzip",
+        "mp3" => "audio/mpeg",
+        "ogg" => "audio/ogg",
+        "wav" => "audio/wav",
+        "mp4" => "video/mp4",
+        "webm" => "video/webm",
+        "webmanifest" => "application/manifest+json",
+        _ => "application/octet-stream",
+    }
+}
+
+// the response for a path that tries to leave the directory
+fn forbidden() -> AppResponse {
+    AppResponse::new(403, "Forbidden").with_header("Content-Type", "text/plain")
+}
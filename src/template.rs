@@ -0,0 +1,535 @@
+// src/template.rs
+
+// dependencies
+use crate::log::log_error;
+use crate::response::AppResponse;
+use std::collections::HashMap;
+use std::path::Path;
+
+// partials and layouts nested deeper than this are treated as a loop
+const MAX_DEPTH: usize = 32;
+
+// enum type to represent the data a template is rendered with
+#[derive(Debug, Clone, Default, PartialEq)]
+pub enum TemplateValue {
+    #[default]
+    Null,
+    Bool(bool),
+    Text(String),
+    List(Vec<TemplateValue>),
+    Map(HashMap<String, TemplateValue>),
+}
+
+// methods for the TemplateValue type
+impl TemplateValue {
+    // an empty map, fill it with .with(key, value)
+    pub fn map() -> Self {
+        TemplateValue::Map(HashMap::new())
+    }
+
+    // add a key to a map, other values are turned into a map first
+    pub fn with(mut self, key: &str, value: impl Into<TemplateValue>) -> Self {
+        if !matches!(self, TemplateValue::Map(_)) {
+            self = TemplateValue::map();
+        }
+        if let TemplateValue::Map(map) = &mut self {
+            map.insert(key.to_string(), value.into());
+        }
+        self
+    }
+
+    // null, false, "" and [] hide a section and show an inverted one
+    fn is_truthy(&self) -> bool {
+        match self {
+            TemplateValue::Null => false,
+            TemplateValue::Bool(value) => *value,
+            TemplateValue::Text(text) => !text.is_empty(),
+            TemplateValue::List(items) => !items.is_empty(),
+            TemplateValue::Map(_) => true,
+        }
+    }
+
+    // build template data from anything serde can serialize, e.g. a struct of page data
+    #[cfg(feature = "serde")]
+    pub fn from_serialize(value: &impl serde::Serialize) -> Result<Self, serde_json::Error> {
+        Ok(serde_json::to_value(value)?.into())
+    }
+}
+
+// implement the From trait to build TemplateValues from common types
+impl From<&str> for TemplateValue {
+    fn from(value: &str) -> Self {
+        TemplateValue::Text(value.to_string())
+    }
+}
+
+impl From<String> for TemplateValue {
+    fn from(value: String) -> Self {
+        TemplateValue::Text(value)
+    }
+}
+
+impl From<bool> for TemplateValue {
+    fn from(value: bool) -> Self {
+        TemplateValue::Bool(value)
+    }
+}
+
+impl From<i64> for TemplateValue {
+    fn from(value: i64) -> Self {
+        TemplateValue::Text(value.to_string())
+    }
+}
+
+impl From<u64> for TemplateValue {
+    fn from(value: u64) -> Self {
+        TemplateValue::Text(value.to_string())
+    }
+}
+
+impl From<i32> for TemplateValue {
+    fn from(value: i32) -> Self {
+        TemplateValue::Text(value.to_string())
+    }
+}
+
+impl From<f64> for TemplateValue {
+    fn from(value: f64) -> Self {
+        TemplateValue::Text(value.to_string())
+    }
+}
+
+impl<T: Into<TemplateValue>> From<Vec<T>> for TemplateValue {
+    fn from(values: Vec<T>) -> Self {
+        TemplateValue::List(values.into_iter().map(Into::into).collect())
+    }
+}
+
+impl<T: Into<TemplateValue>> From<Option<T>> for TemplateValue {
+    fn from(value: Option<T>) -> Self {
+        value.map(Into::into).unwrap_or(TemplateValue::Null)
+    }
+}
+
+impl<T: Into<TemplateValue>> From<HashMap<String, T>> for TemplateValue {
+    fn from(values: HashMap<String, T>) -> Self {
+        TemplateValue::Map(
+            values
+                .into_iter()
+                .map(|(key, value)| (key, value.into()))
+                .collect(),
+        )
+    }
+}
+
+// implement the From trait to convert JSON data into a TemplateValue
+#[cfg(feature = "serde")]
+impl From<serde_json::Value> for TemplateValue {
+    fn from(value: serde_json::Value) -> Self {
+        match value {
+            serde_json::Value::Null => TemplateValue::Null,
+            serde_json::Value::Bool(value) => TemplateValue::Bool(value),
+            serde_json::Value::Number(number) => TemplateValue::Text(number.to_string()),
+            serde_json::Value::String(text) => TemplateValue::Text(text),
+            serde_json::Value::Array(items) => {
+                TemplateValue::List(items.into_iter().map(Into::into).collect())
+            }
+            serde_json::Value::Object(map) => TemplateValue::Map(
+                map.into_iter()
+                    .map(|(key, value)| (key, value.into()))
+                    .collect(),
+            ),
+        }
+    }
+}
+
+// enum type to represent a template which couldn't be compiled or rendered
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TemplateError {
+    // the template's tags don't match up, e.g. a section which is never closed
+    Syntax { template: String, message: String },
+    // a template, partial or layout which was never added
+    NotFound(String),
+    // partials or layouts including each other in a loop
+    TooDeep(String),
+    // a template file which couldn't be read
+    Io(String),
+}
+
+// implement the Display trait for the TemplateError type
+impl std::fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TemplateError::Syntax { template, message } => {
+                write!(f, "syntax error in template {}: {}", template, message)
+            }
+            TemplateError::NotFound(name) => write!(f, "template {} not found", name),
+            TemplateError::TooDeep(name) => {
+                write!(f, "templates nested too deeply while rendering {}", name)
+            }
+            TemplateError::Io(message) => write!(f, "failed to load templates: {}", message),
+        }
+    }
+}
+
+// implement the Error trait for the TemplateError type
+impl std::error::Error for TemplateError {}
+
+// implement the From trait so a failed render can be returned from a handler with .into()
+impl From<TemplateError> for AppResponse {
+    fn from(err: TemplateError) -> Self {
+        log_error!("{}", err);
+        AppResponse::internal_error("Internal Server Error")
+            .with_header("Content-Type", "text/plain")
+    }
+}
+
+// enum type to represent a piece of a compiled template
+#[derive(Debug, Clone, PartialEq)]
+enum Node {
+    Text(String),
+    // {{name}} is escaped, {{{name}}} and {{& name}} are not
+    Variable {
+        path: Vec<String>,
+        escape: bool,
+    },
+    // {{#name}}...{{/name}}, or {{^name}}...{{/name}} when inverted
+    Section {
+        path: Vec<String>,
+        inverted: bool,
+        children: Vec<Node>,
+    },
+    // {{> name}}
+    Partial(String),
+    // {{$name}}default{{/name}}, a block a child template can replace
+    Block {
+        name: String,
+        children: Vec<Node>,
+    },
+    // {{< layout}}{{$name}}...{{/name}}{{/layout}}, render layout with these blocks replaced
+    Layout {
+        name: String,
+        blocks: HashMap<String, Vec<Node>>,
+    },
+}
+
+// struct type to represent a set of mustache-style templates compiled once, usually at startup,
+// which can include each other as partials and layouts
+#[derive(Debug, Clone, Default)]
+pub struct TemplateEngine {
+    templates: HashMap<String, Vec<Node>>,
+}
+
+// methods for the TemplateEngine type
+impl TemplateEngine {
+    pub fn new() -> Self {
+        TemplateEngine::default()
+    }
+
+    // compile a template and register it under name, replacing any earlier one
+    pub fn add(&mut self, name: &str, source: &str) -> Result<&mut Self, TemplateError> {
+        let nodes = parse(name, source)?;
+        self.templates.insert(name.to_string(), nodes);
+        Ok(self)
+    }
+
+    // compile every file under dir, named by their path without the extension, so
+    // dir/admin/users.html becomes "admin/users"
+    pub fn load_dir(&mut self, dir: impl AsRef<Path>) -> Result<&mut Self, TemplateError> {
+        let dir = dir.as_ref();
+        let mut files = Vec::new();
+        collect_files(dir, &mut files).map_err(|err| TemplateError::Io(err.to_string()))?;
+
+        for file in files {
+            let source = std::fs::read_to_string(&file)
+                .map_err(|err| TemplateError::Io(format!("{}: {}", file.display(), err)))?;
+            let relative = file.strip_prefix(dir).unwrap_or(&file).with_extension("");
+            let name = relative
+                .components()
+                .map(|part| part.as_os_str().to_string_lossy())
+                .collect::<Vec<_>>()
+                .join("/");
+            self.add(&name, &source)?;
+        }
+
+        Ok(self)
+    }
+
+    // render a template with data, ready for AppResponse::with_bytes
+    pub fn render(&self, name: &str, data: &TemplateValue) -> Result<Vec<u8>, TemplateError> {
+        let mut output = String::new();
+        let mut renderer = Renderer {
+            engine: self,
+            root: name,
+            stack: vec![data],
+            overrides: Vec::new(),
+        };
+        renderer.template(name, &mut output, 0)?;
+        Ok(output.into_bytes())
+    }
+}
+
+// struct type to represent the state of a single render
+struct Renderer<'a> {
+    engine: &'a TemplateEngine,
+    root: &'a str,
+    // the data in scope, innermost last
+    stack: Vec<&'a TemplateValue>,
+    // block replacements from the templates using a layout, outermost first
+    overrides: Vec<&'a HashMap<String, Vec<Node>>>,
+}
+
+// methods for the Renderer type
+impl<'a> Renderer<'a> {
+    fn template(
+        &mut self,
+        name: &str,
+        output: &mut String,
+        depth: usize,
+    ) -> Result<(), TemplateError> {
+        if depth > MAX_DEPTH {
+            return Err(TemplateError::TooDeep(self.root.to_string()));
+        }
+        let nodes = self
+            .engine
+            .templates
+            .get(name)
+            .ok_or_else(|| TemplateError::NotFound(name.to_string()))?;
+        self.nodes(nodes, output, depth)
+    }
+
+    fn nodes(
+        &mut self,
+        nodes: &'a [Node],
+        output: &mut String,
+        depth: usize,
+    ) -> Result<(), TemplateError> {
+        for node in nodes {
+            match node {
+                Node::Text(text) => output.push_str(text),
+                Node::Variable { path, escape } => {
+                    let text = match self.lookup(path) {
+                        TemplateValue::Text(text) => text.as_str(),
+                        TemplateValue::Bool(true) => "true",
+                        TemplateValue::Bool(false) => "false",
+                        _ => "",
+                    };
+                    if *escape {
+                        escape_html(text, output);
+                    } else {
+                        output.push_str(text);
+                    }
+                }
+                Node::Section {
+                    path,
+                    inverted,
+                    children,
+                } => {
+                    let value = self.lookup(path);
+                    if *inverted {
+                        if !value.is_truthy() {
+                            self.nodes(children, output, depth)?;
+                        }
+                    } else if let TemplateValue::List(items) = value {
+                        for item in items {
+                            self.stack.push(item);
+                            let result = self.nodes(children, output, depth);
+                            self.stack.pop();
+                            result?;
+                        }
+                    } else if value.is_truthy() {
+                        self.stack.push(value);
+                        let result = self.nodes(children, output, depth);
+                        self.stack.pop();
+                        result?;
+                    }
+                }
+                Node::Partial(name) => self.template(name, output, depth + 1)?,
+                Node::Block { name, children } => {
+                    // the outermost template that replaces a block wins
+                    let replacement = self.overrides.iter().find_map(|blocks| blocks.get(name));
+                    self.nodes(replacement.unwrap_or(children), output, depth)?;
+                }
+                Node::Layout { name, blocks } => {
+                    self.overrides.push(blocks);
+                    let result = self.template(name, output, depth + 1);
+                    self.overrides.pop();
+                    result?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // find a dotted name, the first part in the innermost scope which has it, "." is the
+    // current item of a loop
+    fn lookup(&self, path: &[String]) -> &'a TemplateValue {
+        static NULL: TemplateValue = TemplateValue::Null;
+
+        let Some((first, rest)) = path.split_first() else {
+            return self.stack.last().copied().unwrap_or(&NULL);
+        };
+
+        let Some(mut value) = self.stack.iter().rev().find_map(|scope| match scope {
+            TemplateValue::Map(map) => map.get(first),
+            _ => None,
+        }) else {
+            return &NULL;
+        };
+
+        for part in rest {
+            value = match value {
+                TemplateValue::Map(map) => map.get(part).unwrap_or(&NULL),
+                _ => &NULL,
+            };
+        }
+        value
+    }
+}
+
+// compile a template into nodes, checking that every section and block is closed
+fn parse(template: &str, source: &str) -> Result<Vec<Node>, TemplateError> {
+    let syntax = |message: String| TemplateError::Syntax {
+        template: template.to_string(),
+        message,
+    };
+
+    // the open sections, blocks and layouts with the nodes collected inside them so far
+    let mut stack: Vec<(char, String, Vec<Node>)> = vec![(' ', String::new(), Vec::new())];
+    let mut rest = source;
+
+    while let Some(start) = rest.find("{{") {
+        if start > 0 {
+            push_node(&mut stack, Node::Text(rest[..start].to_string()));
+        }
+        rest = &rest[start..];
+
+        // {{{name}}} is the unescaped form of {{name}}
+        let (tag, after) = if let Some(inner) = rest.strip_prefix("{{{") {
+            let end = inner
+                .find("}}}")
+                .ok_or_else(|| syntax("unclosed {{{ tag".to_string()))?;
+            (format!("&{}", &inner[..end]), &inner[end + 3..])
+        } else {
+            let inner = &rest[2..];
+            let end = inner
+                .find("}}")
+                .ok_or_else(|| syntax("unclosed {{ tag".to_string()))?;
+            (inner[..end].to_string(), &inner[end + 2..])
+        };
+        rest = after;
+
+        let tag = tag.trim();
+        let sigil = tag.chars().next().unwrap_or(' ');
+        let name = tag.get(sigil.len_utf8()..).unwrap_or("").trim().to_string();
+
+        match sigil {
+            '!' => {}
+            '#' | '^' | '$' | '<' => stack.push((sigil, name, Vec::new())),
+            '/' => {
+                let (open, open_name, children) = stack.pop().unwrap();
+                if stack.is_empty() || open_name != name {
+                    return Err(syntax(format!("unexpected {{{{/{}}}}}", name)));
+                }
+                let node = match open {
+                    '#' | '^' => Node::Section {
+                        path: split_path(&open_name),
+                        inverted: open == '^',
+                        children,
+                    },
+                    '$' => Node::Block {
+                        name: open_name,
+                        children,
+                    },
+                    // only the blocks inside a layout tag matter
+                    _ => Node::Layout {
+                        name: open_name,
+                        blocks: children
+                            .into_iter()
+                            .filter_map(|node| match node {
+                                Node::Block { name, children } => Some((name, children)),
+                                _ => None,
+                            })
+                            .collect(),
+                    },
+                };
+                push_node(&mut stack, node);
+            }
+            '>' => push_node(&mut stack, Node::Partial(name)),
+            '&' => push_node(
+                &mut stack,
+                Node::Variable {
+                    path: split_path(&name),
+                    escape: false,
+                },
+            ),
+            _ => push_node(
+                &mut stack,
+                Node::Variable {
+                    path: split_path(tag),
+                    escape: true,
+                },
+            ),
+        }
+    }
+
+    if !rest.is_empty() {
+        push_node(&mut stack, Node::Text(rest.to_string()));
+    }
+
+    match stack.pop() {
+        Some((_, _, nodes)) if stack.is_empty() => Ok(nodes),
+        Some((_, name, _)) => Err(syntax(format!("{{{{{}}}}} is never closed", name))),
+        None => Ok(Vec::new()),
+    }
+}
+
+// add a node to the innermost open tag
+fn push_node(stack: &mut [(char, String, Vec<Node>)], node: Node) {
+    if let Some((_, _, nodes)) = stack.last_mut() {
+        nodes.push(node);
+    }
+}
+
+// "user.name" becomes ["user", "name"], "." becomes [] for the current item
+fn split_path(name: &str) -> Vec<String> {
+    if name == "." {
+        return Vec::new();
+    }
+    name.split('.')
+        .map(|part| part.trim().to_string())
+        .collect()
+}
+
+// append text with the characters which mean something in HTML escaped
+fn escape_html(text: &str, output: &mut String) {
+    for c in text.chars() {
+        match c {
+            '&' => output.push_str("&amp;"),
+            '<' => output.push_str("&lt;"),
+            '>' => output.push_str("&gt;"),
+            '"' => output.push_str("&quot;"),
+            '\'' => output.push_str("&#39;"),
+            _ => output.push(c),
+        }
+    }
+}
+
+// every file under dir, skipping hidden ones
+fn collect_files(dir: &Path, files: &mut Vec<std::path::PathBuf>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().starts_with('.'))
+        {
+            continue;
+        }
+        if path.is_dir() {
+            collect_files(&path, files)?;
+        } else {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
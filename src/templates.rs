@@ -0,0 +1,119 @@
+// src/templates.rs
+
+// dependencies
+use crate::body::ResponseBody;
+use crate::log::log_error;
+use crate::request::AppRequest;
+use crate::response::AppResponse;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::SystemTime;
+use tera::{Context, Tera};
+
+// struct type to represent the Tera templates loaded from a directory, see App::templates
+#[derive(Debug)]
+pub(crate) struct Templates {
+    tera: RwLock<Tera>,
+    dir: PathBuf,
+    // reload the templates when a file in dir changes, for development
+    reload: bool,
+    // the newest modification time seen when the templates were last loaded
+    loaded_at: Mutex<Option<SystemTime>>,
+}
+
+// methods for the Templates type
+impl Templates {
+    // load every template under dir, .html, .htm and .xml templates are autoescaped
+    pub(crate) fn load(dir: impl Into<PathBuf>, reload: bool) -> Result<Self, tera::Error> {
+        let dir = dir.into();
+        let glob = format!("{}/**/*", dir.display());
+
+        Ok(Templates {
+            tera: RwLock::new(Tera::new(&glob)?),
+            loaded_at: Mutex::new(newest_modification(&dir)),
+            dir,
+            reload,
+        })
+    }
+
+    pub(crate) fn set_reload(&mut self, reload: bool) {
+        self.reload = reload;
+    }
+
+    // render a template, reloading them all first in dev mode if anything has changed
+    pub(crate) fn render(&self, name: &str, context: &Context) -> Result<String, tera::Error> {
+        if self.reload {
+            let newest = newest_modification(&self.dir);
+            let mut loaded_at = self.loaded_at.lock().unwrap();
+
+            if newest != *loaded_at {
+                self.tera.write().unwrap().full_reload()?;
+                *loaded_at = newest;
+            }
+        }
+
+        self.tera.read().unwrap().render(name, context)
+    }
+}
+
+// struct type to represent the App's templates as carried in the request extensions, so
+// handlers can render without a reference to the App
+#[derive(Debug, Clone)]
+pub(crate) struct TemplatesHandle(pub(crate) Arc<Templates>);
+
+// template methods for the AppRequest type
+impl AppRequest {
+    // a 200 text/html response rendered right away from one of the App's templates, see
+    // App::templates, so middleware, with_etag and the rest see the page itself, a template
+    // that fails to render is answered with a 500
+    pub fn render(&self, name: &str, context: &Context) -> AppResponse {
+        let Some(TemplatesHandle(templates)) = self.extensions.get::<TemplatesHandle>() else {
+            log_error!(
+                "can't render template {}, no templates were loaded with App::templates",
+                name
+            );
+            return template_error();
+        };
+
+        match templates.render(name, context) {
+            Ok(html) => {
+                let mut response = AppResponse::status(200);
+                response.body = ResponseBody::Bytes(html.into_bytes());
+                response.with_header("Content-Type", "text/html; charset=utf-8")
+            }
+            Err(err) => {
+                // tera's errors keep the interesting part in their source
+                let mut message = err.to_string();
+                let mut source = std::error::Error::source(&err);
+                while let Some(cause) = source {
+                    message.push_str(&format!(": {}", cause));
+                    source = cause.source();
+                }
+                log_error!("failed to render template {}: {}", name, message);
+                template_error()
+            }
+        }
+    }
+}
+
+// the plain 500 sent when a template can't be rendered
+fn template_error() -> AppResponse {
+    AppResponse::internal_error("Internal Server Error").with_header("Content-Type", "text/plain")
+}
+
+// the latest modification time of any file under dir, to notice edited, added or removed templates
+fn newest_modification(dir: &Path) -> Option<SystemTime> {
+    let mut newest = std::fs::metadata(dir).and_then(|meta| meta.modified()).ok();
+
+    for entry in std::fs::read_dir(dir).ok()?.flatten() {
+        let path = entry.path();
+        let modified = if path.is_dir() {
+            newest_modification(&path)
+        } else {
+            entry.metadata().and_then(|meta| meta.modified()).ok()
+        };
+        newest = newest.max(modified);
+    }
+
+    newest
+}
@@ -0,0 +1,42 @@
+// src/version.rs
+
+// enum type to represent the HTTP version a request was received with
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Version {
+    Http09,
+    Http10,
+    Http11,
+    Http2,
+    Http3,
+}
+
+// methods for the Version type
+impl Version {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Version::Http09 => "HTTP/0.9",
+            Version::Http10 => "HTTP/1.0",
+            Version::Http11 => "HTTP/1.1",
+            Version::Http2 => "HTTP/2.0",
+            Version::Http3 => "HTTP/3.0",
+        }
+    }
+}
+
+// implement the Display trait for the Version type
+impl std::fmt::Display for Version {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+// function which takes a hyper::Version as input and converts it to a flux-web Version
+pub fn convert_version(v: hyper::Version) -> Version {
+    match v {
+        hyper::Version::HTTP_09 => Version::Http09,
+        hyper::Version::HTTP_10 => Version::Http10,
+        hyper::Version::HTTP_2 => Version::Http2,
+        hyper::Version::HTTP_3 => Version::Http3,
+        _ => Version::Http11,
+    }
+}
@@ -0,0 +1,304 @@
+// src/websocket.rs
+
+// dependencies
+use crate::handler::WsHandler;
+use crate::log::log_error;
+use crate::request::AppRequest;
+use crate::response::AppResponse;
+use futures_util::{SinkExt, StreamExt};
+use hyper::upgrade::OnUpgrade;
+use hyper_util::rt::TokioIo;
+use std::io;
+use std::sync::{Arc, Mutex};
+use tokio::sync::{mpsc, oneshot};
+use tokio_tungstenite::WebSocketStream;
+use tokio_tungstenite::tungstenite::handshake::derive_accept_key;
+use tokio_tungstenite::tungstenite::protocol::frame::coding::CloseCode;
+use tokio_tungstenite::tungstenite::protocol::{CloseFrame, Role};
+use tokio_tungstenite::tungstenite::{self, Message as RawMessage};
+
+// how many outgoing messages can be queued for one client before senders wait
+const SEND_QUEUE: usize = 64;
+
+// enum type to represent a WebSocket message
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Message {
+    Text(String),
+    Binary(Vec<u8>),
+    // pings from the client are answered automatically, they're passed on for information
+    Ping(Vec<u8>),
+    Pong(Vec<u8>),
+    // the close code (1000 is a normal close) and reason, None when the peer sent neither
+    Close(Option<(u16, String)>),
+}
+
+// implement the From trait to convert a tungstenite message into a Message
+impl From<RawMessage> for Message {
+    fn from(message: RawMessage) -> Self {
+        match message {
+            RawMessage::Text(text) => Message::Text(text.to_string()),
+            RawMessage::Binary(data) => Message::Binary(data.to_vec()),
+            RawMessage::Ping(data) => Message::Ping(data.to_vec()),
+            RawMessage::Pong(data) => Message::Pong(data.to_vec()),
+            RawMessage::Close(frame) => {
+                Message::Close(frame.map(|frame| (u16::from(frame.code), frame.reason.to_string())))
+            }
+            // raw frames are never returned while reading
+            RawMessage::Frame(_) => Message::Binary(Vec::new()),
+        }
+    }
+}
+
+// implement the From trait to convert a Message into a tungstenite message
+impl From<Message> for RawMessage {
+    fn from(message: Message) -> Self {
+        match message {
+            Message::Text(text) => RawMessage::text(text),
+            Message::Binary(data) => RawMessage::binary(data),
+            Message::Ping(data) => RawMessage::Ping(data.into()),
+            Message::Pong(data) => RawMessage::Pong(data.into()),
+            Message::Close(frame) => RawMessage::Close(frame.map(|(code, reason)| CloseFrame {
+                code: CloseCode::from(code),
+                reason: reason.into(),
+            })),
+        }
+    }
+}
+
+// struct type to represent a cloneable handle for sending to a WebSocket client, e.g. from
+// another task or a WsRoom, while the handler is waiting in recv()
+#[derive(Debug, Clone)]
+pub struct WsSender {
+    sender: mpsc::Sender<Message>,
+}
+
+// methods for the WsSender type
+impl WsSender {
+    pub async fn send(&self, message: Message) -> io::Result<()> {
+        self.sender
+            .send(message)
+            .await
+            .map_err(|_| io::Error::new(io::ErrorKind::BrokenPipe, "websocket is closed"))
+    }
+
+    // true once the connection has closed and nothing more can be sent
+    pub fn is_closed(&self) -> bool {
+        self.sender.is_closed()
+    }
+}
+
+// struct type to represent an upgraded WebSocket connection, given to the handler of app.ws()
+pub struct WebSocket {
+    request: AppRequest,
+    stream: futures_util::stream::SplitStream<WebSocketStream<TokioIo<hyper::upgrade::Upgraded>>>,
+    sender: WsSender,
+}
+
+// methods for the WebSocket type
+impl WebSocket {
+    // the request which opened the connection, for its path, headers and cookies
+    pub fn request(&self) -> &AppRequest {
+        &self.request
+    }
+
+    // the next message from the client, None once the connection is closed or broken
+    pub async fn recv(&mut self) -> Option<Message> {
+        match self.stream.next().await? {
+            Ok(message) => Some(message.into()),
+            Err(err) => {
+                if !matches!(
+                    err,
+                    tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed
+                ) {
+                    log_error!("websocket {}: {}", self.request.path, err);
+                }
+                None
+            }
+        }
+    }
+
+    pub async fn send(&self, message: Message) -> io::Result<()> {
+        self.sender.send(message).await
+    }
+
+    pub async fn send_text(&self, text: impl Into<String>) -> io::Result<()> {
+        self.send(Message::Text(text.into())).await
+    }
+
+    pub async fn send_binary(&self, data: impl Into<Vec<u8>>) -> io::Result<()> {
+        self.send(Message::Binary(data.into())).await
+    }
+
+    pub async fn ping(&self, data: impl Into<Vec<u8>>) -> io::Result<()> {
+        self.send(Message::Ping(data.into())).await
+    }
+
+    // start the closing handshake with a close code (1000 for a normal close) and reason
+    pub async fn close(&self, code: u16, reason: impl Into<String>) -> io::Result<()> {
+        self.send(Message::Close(Some((code, reason.into())))).await
+    }
+
+    // a handle for sending from elsewhere, e.g. to join a WsRoom
+    pub fn sender(&self) -> WsSender {
+        self.sender.clone()
+    }
+}
+
+// implement the Debug trait for the WebSocket type
+impl std::fmt::Debug for WebSocket {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("WebSocket")
+            .field("path", &self.request.path)
+            .finish()
+    }
+}
+
+// struct type to represent a group of WebSocket clients which can be sent to at once, e.g. a
+// chat room, clients that have disconnected are dropped on the next broadcast
+#[derive(Debug, Clone, Default)]
+pub struct WsRoom {
+    members: Arc<Mutex<Vec<WsSender>>>,
+}
+
+// methods for the WsRoom type
+impl WsRoom {
+    pub fn new() -> Self {
+        WsRoom::default()
+    }
+
+    pub fn join(&self, sender: WsSender) {
+        self.members.lock().unwrap().push(sender);
+    }
+
+    // remove a client, e.g. when its handler returns
+    pub fn leave(&self, sender: &WsSender) {
+        self.members
+            .lock()
+            .unwrap()
+            .retain(|member| !member.sender.same_channel(&sender.sender));
+    }
+
+    // the number of connected clients in the room
+    pub fn len(&self) -> usize {
+        let mut members = self.members.lock().unwrap();
+        members.retain(|member| !member.is_closed());
+        members.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // send a message to every client in the room, a client whose queue is full misses it
+    // rather than holding everyone else up
+    pub fn broadcast(&self, message: Message) {
+        let mut members = self.members.lock().unwrap();
+        members.retain(|member| !member.is_closed());
+
+        for member in members.iter() {
+            let _ = member.sender.try_send(message.clone());
+        }
+    }
+
+    // like broadcast, but skipping one client, e.g. the one who sent the message
+    pub fn broadcast_except(&self, except: &WsSender, message: Message) {
+        let mut members = self.members.lock().unwrap();
+        members.retain(|member| !member.is_closed());
+
+        for member in members
+            .iter()
+            .filter(|member| !member.sender.same_channel(&except.sender))
+        {
+            let _ = member.sender.try_send(message.clone());
+        }
+    }
+}
+
+// answer a WebSocket handshake request with 101 Switching Protocols and hand the connection to
+// the handler once hyper has upgraded it, invalid handshakes get a 400 or 426 instead
+pub(crate) fn upgrade(
+    handler: Arc<dyn WsHandler>,
+    request: AppRequest,
+    on_upgrade: Option<OnUpgrade>,
+) -> AppResponse {
+    let header = |name: &str| request.headers.get(name).map(|value| value.as_str());
+    let has_token = |name: &str, token: &str| {
+        header(name).is_some_and(|value| {
+            value
+                .split(',')
+                .any(|item| item.trim().eq_ignore_ascii_case(token))
+        })
+    };
+
+    if !has_token("connection", "upgrade") || !has_token("upgrade", "websocket") {
+        return AppResponse::new(426, "Upgrade Required")
+            .with_header("Content-Type", "text/plain")
+            .with_header("Upgrade", "websocket")
+            .with_header("Connection", "Upgrade");
+    }
+
+    if header("sec-websocket-version") != Some("13") {
+        return AppResponse::new(426, "Unsupported WebSocket version")
+            .with_header("Content-Type", "text/plain")
+            .with_header("Sec-WebSocket-Version", "13");
+    }
+
+    let (Some(key), Some(on_upgrade)) = (header("sec-websocket-key"), on_upgrade) else {
+        return AppResponse::bad_request("Invalid WebSocket handshake")
+            .with_header("Content-Type", "text/plain");
+    };
+    let accept = derive_accept_key(key.trim().as_bytes());
+
+    tokio::spawn(async move {
+        let upgraded = match on_upgrade.await {
+            Ok(upgraded) => upgraded,
+            Err(err) => {
+                log_error!("websocket upgrade for {} failed: {}", request.path, err);
+                return;
+            }
+        };
+
+        let stream =
+            WebSocketStream::from_raw_socket(TokioIo::new(upgraded), Role::Server, None).await;
+        let (mut sink, stream) = stream.split();
+        let (sender, mut outgoing) = mpsc::channel::<Message>(SEND_QUEUE);
+        let (stop, mut stopped) = oneshot::channel::<()>();
+
+        // the writer task owns the sink, so any number of WsSenders can queue messages
+        tokio::spawn(async move {
+            let mut stopping = false;
+            loop {
+                let message = tokio::select! {
+                    message = outgoing.recv() => message,
+                    // the handler is done (or panicked), take no new messages but send the ones
+                    // already queued, a WsRoom may still hold a sender so recv alone never ends
+                    _ = &mut stopped, if !stopping => {
+                        stopping = true;
+                        outgoing.close();
+                        continue;
+                    }
+                };
+                let Some(message) = message else { break };
+                let closing = matches!(message, Message::Close(_));
+                if sink.send(message.into()).await.is_err() || closing {
+                    break;
+                }
+            }
+            // sends a close frame unless one went out already
+            let _ = sink.close().await;
+        });
+
+        let socket = WebSocket {
+            request,
+            stream,
+            sender: WsSender { sender },
+        };
+        handler.handle(socket).await;
+        let _ = stop.send(());
+    });
+
+    AppResponse::status(101)
+        .with_header("Upgrade", "websocket")
+        .with_header("Connection", "Upgrade")
+        .with_header("Sec-WebSocket-Accept", &accept)
+}
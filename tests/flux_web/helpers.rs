@@ -2,7 +2,7 @@
 
 // dependencies
 use flux_web_lib::App;
-use http_body_util::{BodyExt, Empty};
+use http_body_util::{BodyExt, Empty, Full};
 use hyper::body::Bytes;
 use hyper_util::client::legacy::Client;
 use hyper_util::rt::TokioExecutor;
@@ -97,6 +97,34 @@ pub async fn make_request_with_method_and_headers(
     Ok((status, body, response_headers))
 }
 
+// Helper function to send a request with a body and get the raw response back,
+// keeping repeated response headers such as Set-Cookie
+pub async fn send_request(
+    url: &str,
+    method: &str,
+    headers: Vec<(&str, &str)>,
+    body: Vec<u8>,
+) -> Result<(u16, Vec<u8>, hyper::HeaderMap), Box<dyn std::error::Error + Send + Sync>> {
+    let client = Client::builder(TokioExecutor::new()).build_http();
+
+    let uri: hyper::Uri = url.parse()?;
+    let mut req_builder = hyper::Request::builder().method(method).uri(uri);
+
+    for (key, value) in headers {
+        req_builder = req_builder.header(key, value);
+    }
+
+    let req = req_builder.body(Full::new(Bytes::from(body)))?;
+
+    let res = client.request(req).await?;
+    let status = res.status().as_u16();
+    let response_headers = res.headers().clone();
+
+    let body_bytes = res.into_body().collect().await?.to_bytes();
+
+    Ok((status, body_bytes.to_vec(), response_headers))
+}
+
 // Helper to start server in background
 pub async fn start_test_server(port: u16, app: App) {
     tokio::spawn(async move {
@@ -106,3 +134,16 @@ pub async fn start_test_server(port: u16, app: App) {
     // Give server time to start
     sleep(Duration::from_millis(100)).await;
 }
+
+// Helper function to wait for a log file to reach a number of lines, as access log lines are
+// written in the background
+pub async fn wait_for_lines(path: &std::path::Path, count: usize) -> String {
+    for _ in 0..200 {
+        let contents = std::fs::read_to_string(path).unwrap_or_default();
+        if contents.lines().count() >= count {
+            return contents;
+        }
+        sleep(Duration::from_millis(10)).await;
+    }
+    panic!("{} never reached {} lines", path.display(), count);
+}